@@ -1,3 +1,4 @@
+use crate::stats::Stats;
 use rand::prelude::*;
 use std::ops::Range;
 
@@ -55,7 +56,7 @@ impl<'g> Chromosome<'g> {
         triple_accel::hamming(a, b)
     }
 
-    pub fn crossover(&self, other: &Self) -> (Self, Self) {
+    pub fn crossover(&self, other: &Self, stats: &Stats) -> (Self, Self) {
         // First we clone the father and mother strings.
         // We only need to clone so that we can rotate later. I'd like to get rid of this.
         let father = &self.solution;
@@ -94,23 +95,23 @@ impl<'g> Chromosome<'g> {
 
         // Fill the remaining gaps in the children with elements from the parents,
         // starting from the portion following the transplanted section
-        copy_slice_rotated(&father, rot_left, 0..max_gap, &mut son[max..len]);
-        copy_slice_rotated(&father, rot_left, max_gap..min_gap, &mut son[0..min]);
-        copy_slice_rotated(&mother, rot_left, 0..max_gap, &mut daughter[max..len]);
-        copy_slice_rotated(&mother, rot_left, max_gap..min_gap, &mut daughter[0..min]);
+        copy_slice_rotated(father, rot_left, 0..max_gap, &mut son[max..len]);
+        copy_slice_rotated(father, rot_left, max_gap..min_gap, &mut son[0..min]);
+        copy_slice_rotated(mother, rot_left, 0..max_gap, &mut daughter[max..len]);
+        copy_slice_rotated(mother, rot_left, max_gap..min_gap, &mut daughter[0..min]);
 
         let mut son = Chromosome::new(son, self.goal);
         let mut daughter = Chromosome::new(daughter, self.goal);
 
         // Lastly, we randomly mutate the children before returning
-        son.mutate();
-        daughter.mutate();
+        son.mutate(stats);
+        daughter.mutate(stats);
 
         (son, daughter)
     }
 
     #[inline]
-    fn mutate(&mut self) {
+    fn mutate(&mut self, stats: &Stats) {
         use rand::distributions::Uniform;
 
         let mut rng = thread_rng();
@@ -119,7 +120,8 @@ impl<'g> Chromosome<'g> {
         let index_distribution = Uniform::from(0..self.solution.len());
         let rand_maybe = rng.gen_range(0..100);
 
-        if rand_maybe <= 80 {
+        let applied = rand_maybe <= 80;
+        if applied {
             for _ in 0..3 {
                 mutated[index_distribution.sample(&mut rng)] = random();
             }
@@ -127,7 +129,11 @@ impl<'g> Chromosome<'g> {
 
         let mutate_cost = Self::distance(&mutated, self.goal);
 
-        if mutate_cost < self.cost || rand_maybe < 20 {
+        let kept = mutate_cost < self.cost || rand_maybe < 20;
+        if applied {
+            stats.record(self.cost, mutate_cost, kept);
+        }
+        if kept {
             self.solution = mutated;
             self.cost = mutate_cost;
         }
@@ -142,7 +148,7 @@ impl std::fmt::Display for Chromosome<'_> {
 
 impl PartialOrd for Chromosome<'_> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cost.cmp(&other.cost))
+        Some(self.cmp(other))
     }
 }
 
@@ -2,13 +2,17 @@
 static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 mod chromosome;
+mod stats;
+mod steady;
 
 use chromosome::Chromosome;
 use indicatif::{ProgressBar, ProgressStyle};
 use rand::{distributions::WeightedIndex, prelude::*};
 use rayon::prelude::*;
+use stats::Stats;
 use std::io::{stdin, Read};
 use std::path::PathBuf;
+use steady::{Replacement, SteadyState};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -20,6 +24,12 @@ struct Opt {
     parent_survival_rate: f64,
     #[structopt(short, long, default_value = "128")]
     substring_length: usize,
+    #[structopt(long)]
+    stats: bool,
+    #[structopt(long)]
+    steady_state: Option<usize>,
+    #[structopt(long, default_value = "tournament")]
+    replacement: Replacement,
     #[structopt(default_value = "-")]
     text_file: PathBuf,
 }
@@ -31,14 +41,23 @@ fn main() {
     let generation_size = opt.generation_size;
     assert!((0.0..1.0).contains(&opt.parent_survival_rate));
     let parents_survive = opt.generation_size / (opt.parent_survival_rate * 100.0) as usize;
+    // Offspring are bred in pairs, and the best solution is never replaced
+    let steady_state = opt.steady_state.map(|count| {
+        assert_eq!(count % 2, 0);
+        assert!(0 < count && count < generation_size);
+        SteadyState {
+            count,
+            replacement: opt.replacement,
+        }
+    });
 
-    let goal = if opt.text_file == PathBuf::from("-") {
+    let goal = if opt.text_file.as_os_str() == "-" {
         eprintln!("Reading from stdin");
         let mut input = String::new();
         stdin().read_to_string(&mut input).unwrap();
         input.into_bytes()
     } else {
-        let mut file = std::fs::File::open(opt.text_file).unwrap();
+        let mut file = std::fs::File::open(&opt.text_file).unwrap();
         let mut text = String::new();
         file.read_to_string(&mut text).unwrap();
         text.into_bytes()
@@ -48,15 +67,49 @@ fn main() {
         ProgressStyle::default_spinner().template("{elapsed_precise} | {per_sec} | {wide_msg}"),
     );
 
-    let solution: Vec<u8> = goal.par_chunks(opt.substring_length).flat_map(|substring| {
+    // What the mutations did to every substring
+    let total = Stats::default();
+
+    let solution: Vec<u8> = goal.par_chunks(opt.substring_length).enumerate().flat_map(|(idx, substring)| {
         let mut parents: Vec<Chromosome> =
             std::iter::repeat_with(|| Chromosome::random(substring))
                 .take(generation_size)
                 .collect();
         let mut children: Vec<Chromosome> = Vec::with_capacity(generation_size);
+        let stats = Stats::default();
+        let mut generation = 0;
+        let report = |generation: u64| {
+            let counters = stats.take();
+            if opt.stats {
+                let line = format!("substring {} generation {}: {}", idx, generation, counters);
+                // The bar only prints lines while it's shown
+                if pb.is_hidden() {
+                    eprintln!("{}", line);
+                } else {
+                    pb.println(line);
+                }
+            }
+            total.add(&counters);
+        };
+        // The generation each solution joined the population in, when it's in steady state
+        let mut joined = vec![0; generation_size];
+        if steady_state.is_some() {
+            parents.par_sort_unstable();
+        }
 
         loop {
             pb.set_message(&parents[0].to_string().escape_default().to_string());
+            if let Some(steady_state) = steady_state {
+                if parents[0].cost == 0 {
+                    break;
+                }
+                steady_state.step(&mut parents, &mut joined, generation + 1, &stats);
+                report(generation);
+                generation += 1;
+                pb.inc(1);
+                continue;
+            }
+
             // copy the most successful ones
             parents.par_sort_unstable();
             children.extend_from_slice(&parents[0..parents_survive]);
@@ -85,7 +138,7 @@ fn main() {
                             b = dist.sample(&mut local_rng);
                         }
 
-                        parents[a].crossover(&parents[b])
+                        parents[a].crossover(&parents[b], &stats)
                     })
                     .flat_map(|(a, b)| rayon::iter::once(a).chain(rayon::iter::once(b))),
             );
@@ -93,6 +146,9 @@ fn main() {
             std::mem::swap(&mut parents, &mut children);
             children.clear();
 
+            report(generation);
+            generation += 1;
+
             pb.inc(1);
         }
 
@@ -102,4 +158,7 @@ fn main() {
 
     pb.finish();
     println!("{}", String::from_utf8_lossy(&solution));
+    if opt.stats {
+        eprintln!("total {}", total.take());
+    }
 }
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+/// How often the mutation has been applied and what came of it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counters {
+    pub applications: u64,
+    /// How many applications lowered the cost
+    pub improvements: u64,
+    /// How many applications didn't lower the cost, but were kept anyway
+    pub worse_accepted: u64,
+    /// Sum of the changes in cost the applications made, kept or not
+    pub delta: i64,
+}
+
+impl Counters {
+    fn percent(&self, count: u64) -> f64 {
+        if self.applications == 0 {
+            0.0
+        } else {
+            100.0 * count as f64 / self.applications as f64
+        }
+    }
+
+    /// Average change in cost an application made
+    pub fn average_delta(&self) -> f64 {
+        if self.applications == 0 {
+            0.0
+        } else {
+            self.delta as f64 / self.applications as f64
+        }
+    }
+}
+
+impl fmt::Display for Counters {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "mutation: {} applied, {:.1}% improved, {:.1}% worse kept, {:+.3} cost",
+            self.applications,
+            self.percent(self.improvements),
+            self.percent(self.worse_accepted),
+            self.average_delta()
+        )
+    }
+}
+
+/// Counters the threads breeding a generation can all add to
+#[derive(Debug, Default)]
+pub struct Stats {
+    applications: AtomicU64,
+    improvements: AtomicU64,
+    worse_accepted: AtomicU64,
+    delta: AtomicI64,
+}
+
+impl Stats {
+    /// Records a mutation from a solution costing `before` to one costing `after`, which was
+    /// kept or not
+    pub fn record(&self, before: u32, after: u32, kept: bool) {
+        self.applications.fetch_add(1, Ordering::Relaxed);
+        if after < before {
+            self.improvements.fetch_add(1, Ordering::Relaxed);
+        } else if kept {
+            self.worse_accepted.fetch_add(1, Ordering::Relaxed);
+        }
+        let delta = i64::from(after) - i64::from(before);
+        self.delta.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, counters: &Counters) {
+        let Counters {
+            applications,
+            improvements,
+            worse_accepted,
+            delta,
+        } = *counters;
+        self.applications.fetch_add(applications, Ordering::Relaxed);
+        self.improvements.fetch_add(improvements, Ordering::Relaxed);
+        self.worse_accepted
+            .fetch_add(worse_accepted, Ordering::Relaxed);
+        self.delta.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// The counters so far, which start over from zero
+    pub fn take(&self) -> Counters {
+        Counters {
+            applications: self.applications.swap(0, Ordering::Relaxed),
+            improvements: self.improvements.swap(0, Ordering::Relaxed),
+            worse_accepted: self.worse_accepted.swap(0, Ordering::Relaxed),
+            delta: self.delta.swap(0, Ordering::Relaxed),
+        }
+    }
+}
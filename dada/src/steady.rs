@@ -0,0 +1,119 @@
+use crate::chromosome::Chromosome;
+use crate::stats::Stats;
+use rand::{distributions::WeightedIndex, prelude::*};
+use std::str::FromStr;
+
+/// How many solutions a replacement tournament draws, the worst of them is replaced
+const TOURNAMENT_SIZE: usize = 4;
+
+/// Which solutions the offspring replace in steady state. The best solution never is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Replacement {
+    /// The worst ones
+    Worst,
+    /// The ones that joined the population the longest ago
+    Oldest,
+    /// Any of them, at random
+    Random,
+    /// The worst of a few drawn at random
+    Tournament,
+}
+
+impl FromStr for Replacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "worst" => Ok(Replacement::Worst),
+            "oldest" => Ok(Replacement::Oldest),
+            "random" => Ok(Replacement::Random),
+            "tournament" => Ok(Replacement::Tournament),
+            _ => Err(format!(
+                "unknown replacement {:?}, use worst, oldest, random or tournament",
+                s
+            )),
+        }
+    }
+}
+
+/// Breeds a few offspring each generation, which take the place of as many solutions, rather than
+/// replacing the whole population
+#[derive(Clone, Copy, Debug)]
+pub struct SteadyState {
+    /// How many offspring are bred each generation
+    pub count: usize,
+    pub replacement: Replacement,
+}
+
+impl SteadyState {
+    /// Breeds a generation's offspring from parents picked by their cost, and puts them in place
+    /// of the solutions the replacement policy picks. `joined` holds the generation each solution
+    /// joined the population in. The population has to start with its best solution, and still
+    /// does afterwards.
+    pub fn step(
+        &self,
+        population: &mut [Chromosome],
+        joined: &mut [u64],
+        generation: u64,
+        stats: &Stats,
+    ) {
+        let mut rng = thread_rng();
+        let cost: Vec<f64> = population.iter().map(|c| 1.0 / (c.cost as f64)).collect();
+        let dist = WeightedIndex::new(&cost).unwrap();
+        let mut offspring = Vec::with_capacity(self.count);
+        while offspring.len() < self.count {
+            let a = dist.sample(&mut rng);
+            let mut b = dist.sample(&mut rng);
+            while a == b {
+                b = dist.sample(&mut rng);
+            }
+            let (son, daughter) = population[a].crossover(&population[b], stats);
+            offspring.push(son);
+            offspring.push(daughter);
+        }
+
+        let victims = self.pick_victims(population, joined, &mut rng);
+        for (child, victim) in offspring.into_iter().zip(victims) {
+            population[victim] = child;
+            joined[victim] = generation;
+            if population[victim] < population[0] {
+                population.swap(0, victim);
+                joined.swap(0, victim);
+            }
+        }
+    }
+
+    /// Picks a solution for each of the offspring to replace, never the best one and never the
+    /// same one twice
+    fn pick_victims(
+        &self,
+        population: &[Chromosome],
+        joined: &[u64],
+        rng: &mut impl Rng,
+    ) -> Vec<usize> {
+        let len = population.len();
+        let mut victims: Vec<usize> = Vec::with_capacity(self.count);
+        for _ in 0..self.count.min(len.saturating_sub(1)) {
+            let mut draw = || loop {
+                let idx = rng.gen_range(1..len);
+                if !victims.contains(&idx) {
+                    break idx;
+                }
+            };
+            let victim = match self.replacement {
+                Replacement::Worst => (1..len)
+                    .filter(|idx| !victims.contains(idx))
+                    .max_by_key(|&idx| population[idx].cost),
+                Replacement::Oldest => (1..len)
+                    .filter(|idx| !victims.contains(idx))
+                    .min_by_key(|&idx| joined[idx]),
+                Replacement::Random => Some(draw()),
+                Replacement::Tournament => (0..TOURNAMENT_SIZE)
+                    .map(|_| draw())
+                    .max_by_key(|&idx| population[idx].cost),
+            };
+            victims.extend(victim);
+        }
+        victims
+    }
+}
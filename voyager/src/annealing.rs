@@ -0,0 +1,62 @@
+use crate::chromosome::Chromosome;
+use crate::map::Stop;
+use crate::solver::Move;
+use rand::prelude::*;
+
+/// How many moves are tried each generation
+const MOVES_PER_GENERATION: usize = 256;
+/// The temperature to begin with. A move that makes the score this much worse, relative to the
+/// current one, is accepted about a third of the time.
+const INITIAL_TEMPERATURE: f64 = 0.05;
+/// What the temperature is multiplied by each generation
+const COOLING: f64 = 0.995;
+/// The temperature never drops below this, so the search doesn't freeze entirely
+const MIN_TEMPERATURE: f64 = 1e-5;
+
+/// Simulated annealing over a population of two: the best tour found so far, and the current one
+/// the search moves from
+#[derive(Clone, Debug)]
+pub struct Annealing {
+    temperature: f64,
+}
+
+impl Default for Annealing {
+    fn default() -> Self {
+        Annealing {
+            temperature: INITIAL_TEMPERATURE,
+        }
+    }
+}
+
+impl Annealing {
+    /// Tries a generation's worth of moves from the current tour, accepting worse tours with a
+    /// chance that shrinks as they get worse and as the search cools
+    pub fn step<P: Stop>(&mut self, population: &mut [Chromosome<P>]) {
+        let (best, current) = match population {
+            [best, current, ..] => (best, current),
+            _ => return,
+        };
+        let mut rng = thread_rng();
+        let free = current.problem.mode.free_range(current.solution.len());
+        for _ in 0..MOVES_PER_GENERATION {
+            // Half the moves bring stops closer to their candidates, the rest are anywhere
+            let mv = if rng.gen_bool(0.5) {
+                Move::near(current, free.clone(), &mut rng)
+            } else {
+                None
+            };
+            let neighbor = match mv.or_else(|| Move::random(free.clone(), &mut rng)) {
+                Some(mv) => mv.apply(current),
+                None => return,
+            };
+            let worse = (current.score - neighbor.score) / current.score;
+            if worse <= 0.0 || rng.gen::<f64>() < (-worse / self.temperature).exp() {
+                *current = neighbor;
+                if current.score > best.score {
+                    *best = current.clone();
+                }
+            }
+        }
+        self.temperature = (self.temperature * COOLING).max(MIN_TEMPERATURE);
+    }
+}
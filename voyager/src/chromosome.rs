@@ -1,54 +1,560 @@
-use crate::map::{Map, MapPoint, MapSpace};
+use crate::cvrp::Fleet;
+use crate::genealogy::{Birth, Genealogy, Mutation, Origin, Outcome};
+use crate::local;
+use crate::map::{self, Edit, Map, MapPoint, Metric, Stop, StopIds};
+use crate::orienteering::{Orienteering, Visits};
+use crate::pareto::{self, Objective};
+use crate::schedule::{Schedule, Strategy};
+use crate::spatial::{Grid, Neighbors};
+use crate::stats::Stats;
+use ordered_float::OrderedFloat;
 use rand::prelude::*;
 use rayon::prelude::*;
+use std::collections::HashMap;
+use std::iter::once;
+use std::ops::Range;
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
-pub struct Chromosome {
-    pub solution: Map,
+/// How far back in the tour the repair strategy will try to move a late stop
+const REPAIR_REACH: usize = 16;
+/// Most passes 2-opt makes over a tour looking for improvements
+const TWO_OPT_PASSES: usize = 4;
+/// Longest stretch of a tour 2-opt reverses, so a pass stays close to linear on large maps
+const TWO_OPT_REACH: usize = 1000;
+
+/// How the ends of a tour are treated when scoring it, and which stops the GA operators may move.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TourMode {
+    /// The salesman returns to wherever they started, any stop may come first
+    #[default]
+    Closed,
+    /// There is no return leg, the tour simply ends at its last stop
+    Open,
+    /// The first stop of the map is a depot every tour starts from. If `end` is set the last stop
+    /// of the map is a second depot the tour finishes at, otherwise the tour returns to the first.
+    Depot { end: bool },
+}
+
+impl TourMode {
+    /// Whether the tour has a closing leg from its last stop back to its first
+    #[inline]
+    pub fn returns(self) -> bool {
+        match self {
+            TourMode::Closed => true,
+            TourMode::Open => false,
+            TourMode::Depot { end } => !end,
+        }
+    }
+
+    /// The fewest stops a map needs for there to be tours to search through. A closed tour of two
+    /// stops is the same either way round.
+    pub fn min_stops(self) -> usize {
+        match self {
+            TourMode::Closed => 3,
+            TourMode::Open | TourMode::Depot { .. } => 2,
+        }
+    }
+
+    /// The range of positions in a tour of `len` stops which aren't pinned in place
+    #[inline]
+    pub fn free_range(self, len: usize) -> Range<usize> {
+        match self {
+            TourMode::Closed | TourMode::Open => 0..len,
+            TourMode::Depot { end: false } => 1..len,
+            TourMode::Depot { end: true } => 1..(len - 1),
+        }
+    }
+}
+
+/// Everything about what's being solved besides the stops themselves, shared by a population
+#[derive(Clone, Debug, Default)]
+pub struct Problem<P = MapPoint> {
+    pub mode: TourMode,
+    pub metric: Metric<P>,
+    /// When set the stops are served by a fleet of capacitated vehicles rather than a single
+    /// salesman, and each solution is a giant tour which gets split into routes. This requires
+    /// the depot to be pinned at the start of the tour.
+    pub fleet: Option<Fleet<P>>,
+    /// When set stops have time windows, and serving them late adds to a solution's cost
+    pub schedule: Option<Schedule<P>>,
+    /// When set stops have rewards and the tour a length budget, solutions are then scored by
+    /// the reward they collect rather than by their length
+    pub orienteering: Option<Orienteering<P>>,
+    /// When there are several objectives solutions are compared by Pareto dominance over them
+    /// instead of by their score
+    pub objectives: Vec<Objective>,
+    /// The closest stops to each stop, which local search limits itself to. This has to be
+    /// rebuilt whenever the stops or the metric change.
+    pub neighbors: Arc<Neighbors<P>>,
+    /// What the stops are called where they were loaded from, if they were, so exports can tell
+    /// them apart. Stops added later have none.
+    pub ids: StopIds<P>,
+    /// How many solutions have been scored against the problem, including its edited versions,
+    /// which solvers are compared by. Distances measured to build or improve tours count too, a
+    /// tour's worth of them as one evaluation.
+    pub evaluations: Arc<AtomicU64>,
+    /// When set every solution records where it comes from, so the lineage of the best one can
+    /// be saved
+    pub genealogy: Option<Arc<Genealogy>>,
+}
+
+impl<P: Stop> Problem<P> {
+    /// Keeps the per-stop tables in step with an edit of the map. The metric is left alone, as
+    /// rebuilding it needs the whole map.
+    pub fn edit(&mut self, edit: Edit<P>) {
+        if let Some(fleet) = &mut self.fleet {
+            fleet.edit(edit);
+        }
+        if let Some(schedule) = &mut self.schedule {
+            schedule.edit(edit);
+        }
+        if let Some(orienteering) = &mut self.orienteering {
+            orienteering.edit(edit);
+        }
+        if let Edit::Remove(_) | Edit::Move { .. } = edit {
+            map::edit_table(&mut self.ids, edit, String::new);
+        }
+    }
+
+    /// Counts `distances` measured outside of scoring as the evaluations of tours of `stops` stops
+    /// they add up to, so solvers that build or improve tours stop by stop pay for it too
+    pub fn measured(&self, distances: u64, stops: usize) {
+        let evaluations = distances.div_ceil(stops.max(1) as u64);
+        self.evaluations
+            .fetch_add(evaluations, atomic::Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct Chromosome<P = MapPoint> {
+    pub solution: Map<P>,
     pub score: f64,
+    /// The solution's value for each of the problem's objectives
+    pub objectives: Vec<f64>,
+    pub problem: Arc<Problem<P>>,
+    /// Where the solution comes from, if the problem keeps a genealogy
+    pub birth: Option<Arc<Birth>>,
+    /// The generation it joined the population in, which only a steady-state GA keeps track of
+    pub joined: u64,
+}
+
+impl<P: Clone> Clone for Chromosome<P> {
+    fn clone(&self) -> Self {
+        Chromosome {
+            solution: self.solution.clone(),
+            score: self.score,
+            objectives: self.objectives.clone(),
+            problem: self.problem.clone(),
+            birth: self.birth.clone(),
+            joined: self.joined,
+        }
+    }
+
+    /// Reuses the storage of this chromosome, so offspring can be bred into last generation's
+    /// without allocating
+    fn clone_from(&mut self, source: &Self) {
+        self.solution.clone_from(&source.solution);
+        self.score = source.score;
+        self.objectives.clone_from(&source.objectives);
+        self.problem.clone_from(&source.problem);
+        self.birth.clone_from(&source.birth);
+        self.joined = source.joined;
+    }
+}
+
+/// Buffers the GA operators work in, kept from one generation to the next (one per thread) so
+/// breeding doesn't allocate once they've grown to the size of the map
+#[derive(Clone, Debug, Default)]
+pub struct Scratch<P> {
+    /// Where each stop is in the free stretch of the parent a child is filled from
+    position: HashMap<P, usize>,
+    /// Which positions of that parent hold stops the child already got from the other parent
+    taken: Vec<bool>,
+    /// Stops being reordered by the nearest neighbor mutation
+    segment: Vec<P>,
+    /// The solution before a mutation, with its score and objectives, to go back to if the
+    /// mutation isn't kept
+    backup: Vec<P>,
+    score: f64,
+    objectives: Vec<f64>,
+    /// What the mutations made in these buffers did, until they're taken
+    pub stats: Stats,
 }
 
-impl Chromosome {
+impl<P: Stop> Chromosome<P> {
     #[inline]
-    pub fn new(solution: Map) -> Self {
-        let score = Self::score(&solution);
-        Chromosome { solution, score }
+    pub fn new(solution: Map<P>, problem: Arc<Problem<P>>) -> Self {
+        let mut chromosome = Chromosome {
+            solution,
+            score: 0.0,
+            objectives: Vec::new(),
+            problem,
+            birth: None,
+            joined: 0,
+        };
+        chromosome.rescore();
+        chromosome.born(Origin::Seed, &[]);
+        chromosome
     }
 
-    pub fn random(source: &Map) -> Self {
+    /// Records the solution as just born from `parents`, if the problem keeps a genealogy
+    fn born(&mut self, origin: Origin, parents: &[&Self]) {
+        if let Some(genealogy) = &self.problem.genealogy {
+            let parents = parents
+                .iter()
+                .filter_map(|parent| parent.birth.clone())
+                .collect();
+            self.birth = Some(genealogy.birth(origin, parents));
+        }
+    }
+
+    /// Records what came of a mutation, if the solution was just born
+    fn record(&mut self, mutation: Mutation, outcome: Outcome) {
+        if let Some(birth) = self.birth.as_mut().and_then(Arc::get_mut) {
+            birth.mutations.push((mutation, outcome));
+        }
+    }
+
+    /// Repairs the solution if the problem asks for it, and then scores it
+    fn rescore(&mut self) {
+        self.score = Self::evaluate(&mut self.solution, &self.problem);
+        if !self.problem.objectives.is_empty() {
+            let tours = self.tours();
+            let mut objectives = std::mem::take(&mut self.objectives);
+            objectives.clear();
+            objectives.extend(
+                self.problem
+                    .objectives
+                    .iter()
+                    .map(|objective| objective.measure(self, &tours)),
+            );
+            self.objectives = objectives;
+        }
+    }
+
+    /// Whether a mutation into this solution from one with `score` and `objectives` is an
+    /// improvement
+    fn improves(&self, score: f64, objectives: &[f64]) -> bool {
+        if self.problem.objectives.is_empty() {
+            self.score > score
+        } else {
+            !pareto::dominates(objectives, &self.objectives)
+        }
+    }
+
+    /// Saves the solution in `scratch` before a mutation, see `settle`
+    fn checkpoint(&self, scratch: &mut Scratch<P>) {
+        scratch.backup.clear();
+        scratch.backup.extend_from_slice(&self.solution);
+        scratch.score = self.score;
+        scratch.objectives.clone_from(&self.objectives);
+    }
+
+    /// Rescores the mutated solution, and goes back to the one saved in `scratch` unless it's an
+    /// improvement on it
+    fn settle(&mut self, mutation: Mutation, scratch: &mut Scratch<P>) {
+        self.rescore();
+        let delta = (self.score - scratch.score) / scratch.score;
+        // We allow worse mutations to survive 10% of the time
+        let outcome = if self.improves(scratch.score, &scratch.objectives) {
+            Outcome::Improved
+        } else if thread_rng().gen_range(0..100) < 10 {
+            Outcome::Worse
+        } else {
+            self.solution.copy_from_slice(&scratch.backup);
+            self.score = scratch.score;
+            self.objectives.clone_from(&scratch.objectives);
+            Outcome::Rejected
+        };
+        self.record(mutation, outcome);
+        scratch.stats.record(mutation, outcome, delta);
+    }
+
+    /// A random permutation of `source`, keeping whichever stops the tour mode pins in place
+    pub fn random(source: &Map<P>, problem: &Arc<Problem<P>>) -> Self {
+        let mut solution = source.iter().copied().collect::<Vec<_>>();
+        let free = problem.mode.free_range(solution.len());
+        solution[free].shuffle(&mut thread_rng());
+        Chromosome::new(solution.into(), problem.clone())
+    }
+
+    /// Adapts the solution to edits of the map and rescores it against the edited `problem`.
+    /// New stops go wherever they lengthen the tour the least, the rest of the order is kept.
+    pub fn edited(self, edits: &[Edit<P>], problem: Arc<Problem<P>>) -> Self {
+        let mut solution = self.solution;
+        for &edit in edits {
+            match edit {
+                Edit::Add(point) => {
+                    let metric = &problem.metric;
+                    let returns = problem.mode.returns();
+                    let len = solution.len();
+                    let detour = |idx: usize| {
+                        let prev = match idx {
+                            0 => Some(solution[len - 1]).filter(|_| returns),
+                            _ => Some(solution[idx - 1]),
+                        };
+                        let next = match solution.get(idx) {
+                            Some(next) => Some(*next),
+                            None => Some(solution[0]).filter(|_| returns),
+                        };
+                        match (prev, next) {
+                            (Some(a), Some(b)) => {
+                                metric.distance(a, point) + metric.distance(point, b)
+                                    - metric.distance(a, b)
+                            }
+                            (Some(a), None) | (None, Some(a)) => metric.distance(a, point),
+                            (None, None) => 0.0,
+                        }
+                    };
+                    let free = problem.mode.free_range(len);
+                    let idx = (free.start..=free.end)
+                        .min_by_key(|&idx| OrderedFloat(detour(idx)))
+                        .unwrap();
+                    solution.insert(idx, point);
+                }
+                Edit::Remove(_) | Edit::Move { .. } => solution.edit(edit),
+            }
+        }
+        // The edited solution is still the same one as far as its genealogy goes
+        let mut edited = Chromosome {
+            solution,
+            score: 0.0,
+            objectives: Vec::new(),
+            problem,
+            birth: self.birth,
+            joined: self.joined,
+        };
+        edited.rescore();
+        edited
+    }
+
+    /// A nearest neighbor tour of `source` from a random stop, or from the depot if there is one
+    pub fn greedy(source: &Map<P>, problem: &Arc<Problem<P>>) -> Self {
         let mut solution = source.iter().copied().collect::<Vec<_>>();
-        solution.shuffle(&mut thread_rng());
-        Chromosome::new(solution.into())
+        let free = problem.mode.free_range(solution.len());
+        solution[free.clone()].shuffle(&mut thread_rng());
+        // Only what follows the first stop gets reordered, which is the depot if it's pinned
+        nearest_neighbor_order(
+            &mut solution[..free.end],
+            &problem.neighbors,
+            &mut HashMap::new(),
+        );
+        Chromosome::new(solution.into(), problem.clone())
     }
 
     #[inline(always)]
-    fn score(path: &Map) -> f64 {
-        fn repack_point<P: Into<f64>>(
-            p: euclid::Point2D<P, MapSpace>,
-        ) -> euclid::Point2D<f64, MapSpace> {
-            let x = p.x.into();
-            let y = p.y.into();
-            euclid::Point2D::new(x, y)
+    fn score(path: &Map<P>, problem: &Problem<P>) -> f64 {
+        if let Some(orienteering) = &problem.orienteering {
+            // Shorter tours only break ties between equally rewarding ones
+            let visits = orienteering.decode(path, problem.mode, &problem.metric);
+            return f64::from(visits.reward) + 1.0 / (1.0 + visits.length);
+        }
+        1.0 / Self::cost(path, problem)
+    }
+
+    /// Repairs `path` if the problem asks for it, and then scores it
+    #[inline]
+    fn evaluate(path: &mut Map<P>, problem: &Problem<P>) -> f64 {
+        problem.evaluations.fetch_add(1, atomic::Ordering::Relaxed);
+        Self::repair(path, problem);
+        Self::score(path, problem)
+    }
+
+    fn cost(path: &Map<P>, problem: &Problem<P>) -> f64 {
+        if let Some(fleet) = &problem.fleet {
+            return fleet
+                .split(path, &problem.metric, problem.schedule.as_ref())
+                .cost;
         }
+
         let mut cost: f64 = path
             .0
             .par_windows(2)
-            .map(|window| (repack_point(window[0]), repack_point(window[1])))
-            .map(|subpath| subpath.0.distance_to(subpath.1))
+            .map(|window| problem.metric.distance(window[0], window[1]))
             .sum();
-        let start = repack_point(path.0[0]);
-        let end = repack_point(*path.0.last().unwrap());
-        cost += end.distance_to(start);
-        1.0 / cost
+        let start = path.0[0];
+        let end = *path.0.last().unwrap();
+        if problem.mode.returns() {
+            cost += problem.metric.distance(end, start);
+        }
+        if let Some(schedule) = &problem.schedule {
+            let closing = Some(start).filter(|_| problem.mode.returns());
+            let clock = schedule.walk(&problem.metric, path.iter().copied().chain(closing));
+            cost += schedule.strategy.weight() * clock.lateness;
+        }
+        cost
     }
 
-    pub fn crossover(self, other: Self) -> (Self, Self) {
-        // First we clone the father and mother strings.
-        let mut father = self.solution;
-        let mut mother = other.solution;
+    /// Position of the first stop in `path` that's served late
+    fn first_late(path: &Map<P>, problem: &Problem<P>, schedule: &Schedule<P>) -> Option<usize> {
+        let first = path[0];
+        match &problem.fleet {
+            Some(fleet) => fleet
+                .split(path, &problem.metric, Some(schedule))
+                .routes
+                .into_iter()
+                .find_map(|route| {
+                    let mut clock = schedule.start(first);
+                    let mut from = first;
+                    route.into_iter().find(|&idx| {
+                        let served = schedule.travel(clock, &problem.metric, from, path[idx]);
+                        let late = served.violations > clock.violations;
+                        clock = served;
+                        from = path[idx];
+                        late
+                    })
+                }),
+            None => {
+                let mut clock = schedule.start(first);
+                (1..path.len()).find(|&idx| {
+                    let served = schedule.travel(clock, &problem.metric, path[idx - 1], path[idx]);
+                    let late = served.violations > clock.violations;
+                    clock = served;
+                    late
+                })
+            }
+        }
+    }
+
+    /// Moves the first late stop to wherever shortly before it gives the cheapest tour
+    fn repair(path: &mut Map<P>, problem: &Problem<P>) {
+        let schedule = match &problem.schedule {
+            Some(schedule) if matches!(schedule.strategy, Strategy::Repair { .. }) => schedule,
+            _ => return,
+        };
+        let free = problem.mode.free_range(path.len());
+        let late = match Self::first_late(path, problem, schedule) {
+            Some(late) if free.start < late && late < free.end => late,
+            _ => return,
+        };
 
-        debug_assert_eq!(father.len(), mother.len());
+        let mut best = (Self::cost(path, problem), late);
+        let targets = free.start.max(late.saturating_sub(REPAIR_REACH))..late;
+        // Every move tried is scored in full, so each counts as an evaluation on top of the one
+        // scoring the repaired path
+        problem
+            .evaluations
+            .fetch_add(1 + targets.len() as u64, atomic::Ordering::Relaxed);
+        for target in targets {
+            path[target..=late].rotate_right(1);
+            let cost = Self::cost(path, problem);
+            path[target..=late].rotate_left(1);
+            if cost < best.0 {
+                best = (cost, target);
+            }
+        }
+        path[best.1..=late].rotate_right(1);
+    }
+
+    /// Which positions of the solution are visited, if the problem is an orienteering one
+    pub fn visits(&self) -> Option<Visits> {
+        let orienteering = self.problem.orienteering.as_ref()?;
+        Some(orienteering.decode(&self.solution, self.problem.mode, &self.problem.metric))
+    }
+
+    /// The stops each vehicle visits in order, including the depot at either end of a route and
+    /// the first stop again at the end of a closed tour
+    pub fn tours(&self) -> Vec<Vec<P>> {
+        let depot = self.solution[0];
+        if let Some(routes) = self.routes() {
+            return routes
+                .into_iter()
+                .map(|route| {
+                    once(depot)
+                        .chain(route.iter().copied())
+                        .chain(once(depot))
+                        .collect()
+                })
+                .collect();
+        }
+        let visits = self.visits();
+        let closing = Some(depot).filter(|_| self.problem.mode.returns());
+        let tour = self
+            .solution
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| visits.as_ref().is_none_or(|v| v.visited[*idx]))
+            .map(|(_, point)| *point)
+            .chain(closing)
+            .collect();
+        vec![tour]
+    }
+
+    /// Total distance travelled by the solution, across every vehicle if there's a fleet and
+    /// only through the visited stops if it's an orienteering problem
+    pub fn length(&self) -> f64 {
+        let metric = &self.problem.metric;
+        self.tours()
+            .into_iter()
+            .map(|tour| metric.length(tour))
+            .sum()
+    }
+
+    /// How many stops the solution serves late, if the problem has time windows
+    pub fn violations(&self) -> Option<usize> {
+        let schedule = self.problem.schedule.as_ref()?;
+        let depot = self.solution[0];
+        let violations = match self.routes() {
+            Some(routes) => routes
+                .into_iter()
+                .map(|route| {
+                    let stops = once(depot).chain(route.iter().copied()).chain(once(depot));
+                    schedule.walk(&self.problem.metric, stops).violations
+                })
+                .sum(),
+            None => {
+                let closing = Some(depot).filter(|_| self.problem.mode.returns());
+                schedule
+                    .walk(
+                        &self.problem.metric,
+                        self.solution.iter().copied().chain(closing),
+                    )
+                    .violations
+            }
+        };
+        Some(violations)
+    }
+
+    /// The vehicle routes this solution is split into, if the problem has a fleet
+    pub fn routes(&self) -> Option<Vec<&[P]>> {
+        let fleet = self.problem.fleet.as_ref()?;
+        let routes = fleet
+            .split(
+                &self.solution,
+                &self.problem.metric,
+                self.problem.schedule.as_ref(),
+            )
+            .routes
+            .into_iter()
+            .map(|route| &self.solution[route])
+            .collect();
+        Some(routes)
+    }
+
+    /// Breeds `son` and `daughter` from this solution and `other` with order crossover, reusing
+    /// whatever storage they already have
+    pub fn crossover(
+        &self,
+        other: &Self,
+        son: &mut Self,
+        daughter: &mut Self,
+        scratch: &mut Scratch<P>,
+    ) {
+        debug_assert!(Arc::ptr_eq(&self.problem, &other.problem));
+        debug_assert_eq!(self.solution.len(), other.solution.len());
+        son.clone_from(self);
+        daughter.clone_from(other);
+        son.born(Origin::OrderCrossover, &[self, other]);
+        daughter.born(Origin::OrderCrossover, &[self, other]);
+
+        // Pinned stops are identical in both parents, so we only recombine what's between them
+        let free = self.problem.mode.free_range(self.solution.len());
+        let (father, mother) = (&self.solution[free.clone()], &other.solution[free.clone()]);
         let len = father.len();
+        if len < 2 {
+            return;
+        }
 
         // Now we pick two random cutting points which will be identical for the father and mother
         let mut rng = thread_rng();
@@ -62,135 +568,294 @@ impl Chromosome {
             std::mem::swap(&mut min, &mut max);
         }
 
-        // Prepare to construct the offsprint from the crossover
-        let mut son = Map::from(vec![MapPoint::default(); len]);
-        let mut daughter = Map::from(vec![MapPoint::default(); len]);
-
-        // Copy the middle portions as-is
-        son[min..max].copy_from_slice(&mother[min..max]);
-        daughter[min..max].copy_from_slice(&father[min..max]);
-
-        // Rotate parents so they start after the maximum cut-point
-        father.rotate_left(max);
-        mother.rotate_left(max);
+        let cuts = (min, max);
+        Self::order_crossover(
+            father,
+            mother,
+            &mut son.solution[free.clone()],
+            cuts,
+            scratch,
+        );
+        Self::order_crossover(mother, father, &mut daughter.solution[free], cuts, scratch);
+        son.rescore();
+        daughter.rescore();
+    }
 
-        // Filter nodes already present in offspring from parents (swapped parents wrt the middle
-        // copy)
-        father.retain(|point| !son.contains(point));
-        mother.retain(|point| !daughter.contains(point));
+    /// Fills `child` from two parents: between the cut points it gets the stops of `donor` as
+    /// they are, and the rest of its positions are filled in the order of `parent`, starting
+    /// after the second cut point and skipping the stops the donor already gave it
+    fn order_crossover(
+        parent: &[P],
+        donor: &[P],
+        child: &mut [P],
+        (min, max): (usize, usize),
+        scratch: &mut Scratch<P>,
+    ) {
+        let len = parent.len();
+        scratch.position.clear();
+        scratch
+            .position
+            .extend(parent.iter().enumerate().map(|(idx, &stop)| (stop, idx)));
+        scratch.taken.clear();
+        scratch.taken.resize(len, false);
+        for stop in &donor[min..max] {
+            scratch.taken[scratch.position[stop]] = true;
+        }
 
-        // Finally, copy over remaining nodes
-        // The upper portion
-        let upper_cut = len - max;
-        son[max..].copy_from_slice(&father.drain(0..upper_cut).as_slice());
-        daughter[max..].copy_from_slice(&mother.drain(0..upper_cut).as_slice());
-        // The lower portion
-        son[..min].copy_from_slice(&father.drain(..).as_slice());
-        daughter[..min].copy_from_slice(&mother.drain(..).as_slice());
+        // Copy the middle portion as-is
+        child[min..max].copy_from_slice(&donor[min..max]);
 
-        (son.into(), daughter.into())
+        // Then the rest of the parent, starting after the maximum cut-point, into the upper
+        // portion and wrapping around to the lower one
+        let mut slot = max;
+        for idx in (max..len).chain(0..max) {
+            if !scratch.taken[idx] {
+                child[slot] = parent[idx];
+                slot = (slot + 1) % len;
+            }
+        }
     }
 
     #[inline]
-    pub fn mutate(&mut self) {
+    pub fn mutate(&mut self, scratch: &mut Scratch<P>) {
         let mut rng = thread_rng();
         let rand_maybe = rng.gen_range(0..100);
 
         // We swap mutate with a 80% probability
         if rand_maybe < 80 {
-            Self::random_swap(self);
+            Self::random_swap(self, scratch);
         }
 
         // We apply the NN-mutation with 30% probability
         if rand_maybe < 30 {
-            Self::nearest_neighbor(self);
+            Self::nearest_neighbor(self, scratch);
+        }
+
+        // And search the 2-opt neighborhood with 10% probability
+        if rng.gen_range(0..100) < 10 {
+            Self::two_opt(self, scratch);
         }
     }
 
     #[inline]
-    fn random_swap(&mut self) {
+    fn random_swap(&mut self, scratch: &mut Scratch<P>) {
         use rand::distributions::Uniform;
         let mut rng = thread_rng();
-        let mut mutated = self.clone();
-        let index_distribution = Uniform::from(0..self.solution.len());
-        let swaps = rng.gen_range(0..(self.solution.len() / 2));
+        let free = self.problem.mode.free_range(self.solution.len());
+        if free.len() < 2 {
+            return;
+        }
+        self.checkpoint(scratch);
+        let index_distribution = Uniform::from(free.clone());
+        let swaps = rng.gen_range(0..(free.len() / 2));
         for _ in 0..swaps {
             let a = index_distribution.sample(&mut rng);
             let b = index_distribution.sample(&mut rng);
-            mutated.solution.swap(a, b)
-        }
-        mutated.score = Self::score(&mutated.solution);
-
-        // We allow worse mutations to survive 10% of the time
-        if mutated.score > self.score || rng.gen_range(0..100) < 10 {
-            std::mem::swap(self, &mut mutated);
-        }
-    }
-
-    fn nearest_neighbor(&mut self) {
-        fn optimize_subgraph(m: &mut [MapPoint]) {
-            let len = m.len();
-            for idx in 1..len {
-                let reference = m[idx - 1];
-                let best_idx = &m[idx..len]
-                    .into_par_iter()
-                    .enumerate()
-                    .min_by_key(|(_, e)| e.distance_to(reference))
-                    .map(|(idx, _)| idx)
-                    .unwrap();
-                m.swap(idx, *best_idx);
-            }
+            self.solution.swap(a, b)
         }
+        self.settle(Mutation::RandomSwap, scratch);
+    }
 
+    fn nearest_neighbor(&mut self, scratch: &mut Scratch<P>) {
         let mut rng = thread_rng();
-        let mut mutated = self.clone();
-        let graph = &mut mutated.solution;
+        let free = self.problem.mode.free_range(self.solution.len());
+        if free.len() < 3 {
+            return;
+        }
+        self.checkpoint(scratch);
+        // Pinned stops are left out of the graph, so they're never moved
+        let graph = &mut self.solution[free];
         // We only apply the heuristc to a subgraph representing about 1/3 of the overall TSP
         let subgraph_len = graph.len() / 3;
+        let neighbors = &self.problem.neighbors;
+        let position = &mut scratch.position;
 
         let pivot_point = rng.gen_range(0..graph.len());
         // Either our pivot point has enough headroom that we can have a simple subgraph,
         // or we need to construct the subgraph from an end portion and a start portion.
         if pivot_point + subgraph_len < graph.len() {
-            optimize_subgraph(&mut graph[pivot_point..(pivot_point + subgraph_len)]);
+            nearest_neighbor_order(
+                &mut graph[pivot_point..(pivot_point + subgraph_len)],
+                neighbors,
+                position,
+            );
         } else {
-            let mut subgraph = Map::from(vec![MapPoint::default(); subgraph_len]);
+            let subgraph = &mut scratch.segment;
             let end_len = graph.len() - pivot_point;
-            subgraph[0..end_len].copy_from_slice(&graph[pivot_point..]);
             let start_len = subgraph_len - end_len;
-            subgraph[end_len..(end_len + start_len)].copy_from_slice(&graph[0..start_len]);
-            optimize_subgraph(&mut subgraph);
+            subgraph.clear();
+            subgraph.extend_from_slice(&graph[pivot_point..]);
+            subgraph.extend_from_slice(&graph[0..start_len]);
+            nearest_neighbor_order(subgraph, neighbors, position);
             graph[pivot_point..].copy_from_slice(&subgraph[0..end_len]);
             graph[0..start_len].copy_from_slice(&subgraph[end_len..(end_len + start_len)]);
         }
 
-        mutated.score = Self::score(&graph);
-        // We allow worse mutations to survive 10% of the time
-        if mutated.score > self.score || rng.gen_range(0..100) < 10 {
-            std::mem::swap(self, &mut mutated);
+        self.settle(Mutation::NearestNeighbor, scratch);
+    }
+
+    /// Polishes the solution with Or-opt and Lin-Kernighan moves until neither shortens it any
+    /// further, keeping the result if it's an improvement. The moves only look at the length, so
+    /// with time windows, a fleet or rewards the result may not be.
+    pub fn optimize(&mut self) {
+        let mut optimized = self.clone();
+        if !local::optimize(&mut optimized.solution, &self.problem) {
+            return;
+        }
+        optimized.rescore();
+        if optimized.improves(self.score, &self.objectives) {
+            optimized.born(Origin::Polish, &[self]);
+            std::mem::swap(self, &mut optimized);
+        }
+    }
+
+    /// Reverses stretches of the tour wherever that shortens it, only trying to connect each stop
+    /// to its candidates
+    fn two_opt(&mut self, scratch: &mut Scratch<P>) {
+        let free = self.problem.mode.free_range(self.solution.len());
+        let len = free.len();
+        if len < 4 {
+            return;
+        }
+        self.checkpoint(scratch);
+        let tour = &mut self.solution[free];
+        let metric = &self.problem.metric;
+        let mut measured = 0;
+        let position = &mut scratch.position;
+        position.clear();
+        position.extend(tour.iter().enumerate().map(|(idx, pt)| (*pt, idx)));
+
+        for _ in 0..TWO_OPT_PASSES {
+            let mut improved = false;
+            for i in 0..(len - 1) {
+                let (a, b) = (tour[i], tour[i + 1]);
+                for &c in self.problem.neighbors.of(a) {
+                    // Connecting a to c and b to d means reversing everything from b to c
+                    let j = match position.get(&c) {
+                        Some(&j) if j > i + 1 && j + 1 < len && j - i <= TWO_OPT_REACH => j,
+                        _ => continue,
+                    };
+                    let d = tour[j + 1];
+                    measured += 4;
+                    let delta = metric.distance(a, c) + metric.distance(b, d)
+                        - metric.distance(a, b)
+                        - metric.distance(c, d);
+                    if delta < -f64::EPSILON {
+                        tour[(i + 1)..=j].reverse();
+                        for (idx, pt) in tour.iter().enumerate().take(j + 1).skip(i + 1) {
+                            position.insert(*pt, idx);
+                        }
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+
+        self.problem.measured(measured, len);
+        self.settle(Mutation::TwoOpt, scratch);
+    }
+}
+
+/// Breeds `offspring` in pairs from the parents `pick` picks, reusing whatever storage the
+/// offspring already have. The offspring are split between the threads, each breeding into its
+/// own scratch buffers from `scratch`, which gets more of them if there are more threads.
+pub fn breed<P: Stop>(
+    parents: &[Chromosome<P>],
+    offspring: &mut [Chromosome<P>],
+    scratch: &mut Vec<Scratch<P>>,
+    pick: impl Fn(&mut ThreadRng) -> (usize, usize) + Sync,
+) {
+    let threads = rayon::current_num_threads();
+    if scratch.len() < threads {
+        scratch.resize_with(threads, Scratch::default);
+    }
+    let pairs = (offspring.len() / 2).div_ceil(threads).max(1);
+    offspring
+        .par_chunks_mut(2 * pairs)
+        .zip(scratch.par_iter_mut())
+        .for_each(|(chunk, scratch)| {
+            let mut rng = thread_rng();
+            for pair in chunk.chunks_exact_mut(2) {
+                let (a, b) = pick(&mut rng);
+                if let [son, daughter] = pair {
+                    parents[a].crossover(&parents[b], son, daughter, scratch);
+                    son.mutate(scratch);
+                    daughter.mutate(scratch);
+                }
+            }
+        });
+}
+
+/// Reorders everything after the first stop of `m` so each stop is followed by the closest one
+/// left. The candidates usually hold it, otherwise it's looked up in a grid of what's left.
+/// `position` is overwritten with where each stop still to be placed is.
+fn nearest_neighbor_order<P: Stop>(
+    m: &mut [P],
+    neighbors: &Neighbors<P>,
+    position: &mut HashMap<P, usize>,
+) {
+    let len = m.len();
+    if len < 3 {
+        return;
+    }
+    position.clear();
+    position.extend(m.iter().enumerate().skip(1).map(|(idx, pt)| (*pt, idx)));
+    let mut grid: Option<Grid<P>> = None;
+    for idx in 1..len {
+        let reference = m[idx - 1];
+        let best = neighbors
+            .of(reference)
+            .iter()
+            .find_map(|candidate| position.get(candidate).copied())
+            .unwrap_or_else(|| {
+                let grid = grid.get_or_insert_with(|| Grid::new(&m[idx..]));
+                let nearest = grid.nearest(reference).unwrap_or(m[idx]);
+                position[&nearest]
+            });
+        m.swap(idx, best);
+        position.remove(&m[idx]);
+        if best != idx {
+            position.insert(m[best], best);
+        }
+        if let Some(grid) = &mut grid {
+            grid.remove(m[idx]);
         }
     }
 }
 
-impl From<Map> for Chromosome {
-    fn from(map: Map) -> Self {
-        Self::new(map)
+impl<P: Stop> From<Map<P>> for Chromosome<P> {
+    fn from(map: Map<P>) -> Self {
+        Self::new(map, Arc::default())
     }
 }
 
-impl std::fmt::Display for Chromosome {
+impl<P: Stop> std::fmt::Display for Chromosome<P> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(fmt, "{}", self.solution)
     }
 }
 
-impl PartialOrd for Chromosome {
+impl<P: Stop> std::str::FromStr for Chromosome<P> {
+    type Err = Box<dyn std::error::Error>;
+
+    /// Parses a tour the way it's displayed, scored against the default problem like a map
+    /// converted into one
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse::<Map<P>>()?.into())
+    }
+}
+
+impl<P> PartialOrd for Chromosome<P> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.score.partial_cmp(&other.score)
+        Some(self.cmp(other))
     }
 }
 
-impl Ord for Chromosome {
+impl<P> Ord for Chromosome<P> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.score
             .partial_cmp(&other.score)
@@ -198,10 +863,10 @@ impl Ord for Chromosome {
     }
 }
 
-impl PartialEq for Chromosome {
+impl<P> PartialEq for Chromosome<P> {
     fn eq(&self, other: &Self) -> bool {
         self.score == other.score
     }
 }
 
-impl Eq for Chromosome {}
+impl<P> Eq for Chromosome<P> {}
@@ -0,0 +1,161 @@
+use crate::chromosome::Chromosome;
+use crate::map::{Metric, Stop};
+use ordered_float::OrderedFloat;
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// How many tours the colony keeps, the best one so far and one built by each ant
+pub const ANTS: usize = 32;
+/// How much the pheromone on an edge weighs in an ant's choice of the next stop
+const ALPHA: f64 = 1.0;
+/// How much the closeness of the next stop weighs in it
+const BETA: f64 = 3.0;
+/// Share of the pheromone that evaporates each generation
+const EVAPORATION: f64 = 0.1;
+
+/// An edge between two stops, whichever way it's travelled
+fn edge<P: Stop>(a: P, b: P) -> (P, P) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// The edges a solution travels, including the closing one if it returns to its start
+fn edges<P: Stop>(chromosome: &Chromosome<P>) -> impl Iterator<Item = (P, P)> + '_ {
+    let solution = &chromosome.solution;
+    let closing = Some((solution[solution.len() - 1], solution[0]))
+        .filter(|_| chromosome.problem.mode.returns() && solution.len() > 1);
+    solution
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .chain(closing)
+        .map(|(a, b)| edge(a, b))
+}
+
+/// A MAX-MIN ant system. Each generation every ant but one builds a tour, and the best of them
+/// along with the best tour so far lay pheromone on the edges they travel, which is kept between
+/// bounds so the search never settles on a single tour entirely.
+#[derive(Clone, Debug, Default)]
+pub struct Colony<P> {
+    /// Pheromone on the edges between each stop and its candidates, and on any edge good tours
+    /// travelled since
+    pheromone: HashMap<(P, P), f64>,
+    /// The least and the most pheromone an edge can have, edges that have none recorded have
+    /// the least
+    bounds: (f64, f64),
+}
+
+impl<P: Stop> Colony<P> {
+    /// Sets the bounds from the best tour so far
+    fn bound(&mut self, best: &Chromosome<P>) {
+        let max = best.score / EVAPORATION;
+        self.bounds = (max / (2 * best.solution.len()) as f64, max);
+    }
+
+    /// How strongly an ant at `a` is drawn to `b`
+    fn attraction(&self, a: P, b: P, metric: &Metric<P>) -> f64 {
+        let pheromone = self
+            .pheromone
+            .get(&edge(a, b))
+            .copied()
+            .unwrap_or(self.bounds.0);
+        let closeness = 1.0 / metric.distance(a, b).max(f64::EPSILON);
+        pheromone.powf(ALPHA) * closeness.powf(BETA)
+    }
+
+    /// Builds a tour through the stops of `template`, keeping the ones its tour mode pins in
+    /// place. Each next stop is drawn from the candidates of the last one, or is the closest stop
+    /// left when they've all been visited.
+    fn construct(&self, template: &Chromosome<P>, rng: &mut impl Rng) -> Chromosome<P> {
+        let problem = &template.problem;
+        let free = problem.mode.free_range(template.solution.len());
+        let mut unvisited: HashSet<P> = template.solution[free.clone()].iter().copied().collect();
+        let mut tour = template.solution[..free.start].to_vec();
+        // How many distances picking each next stop measured
+        let mut measured = 0;
+        if tour.is_empty() {
+            let start = template.solution[rng.gen_range(free.clone())];
+            unvisited.remove(&start);
+            tour.push(start);
+        }
+        while !unvisited.is_empty() {
+            let at = tour[tour.len() - 1];
+            let candidates: Vec<P> = problem
+                .neighbors
+                .of(at)
+                .iter()
+                .copied()
+                .filter(|stop| unvisited.contains(stop))
+                .collect();
+            let weights = candidates
+                .iter()
+                .map(|&stop| self.attraction(at, stop, &problem.metric));
+            measured += candidates.len();
+            let next = match WeightedIndex::new(weights) {
+                Ok(dist) => candidates[dist.sample(rng)],
+                Err(_) => {
+                    measured += unvisited.len();
+                    *unvisited
+                        .iter()
+                        .min_by_key(|&&stop| OrderedFloat(problem.metric.distance(at, stop)))
+                        .unwrap()
+                }
+            };
+            unvisited.remove(&next);
+            tour.push(next);
+        }
+        tour.extend_from_slice(&template.solution[free.end..]);
+        problem.measured(measured as u64, tour.len());
+        Chromosome::new(tour.into(), problem.clone())
+    }
+
+    /// Lays `amount` of pheromone on the edges `chromosome` travels
+    fn deposit(&mut self, chromosome: &Chromosome<P>, amount: f64) {
+        for edge in edges(chromosome) {
+            *self.pheromone.entry(edge).or_insert(self.bounds.0) += amount;
+        }
+    }
+
+    /// Lets the ants build a generation of tours, which replace all of `population` but its best
+    /// tour, and lays pheromone along the best of them and the best so far
+    pub fn step(&mut self, population: &mut Vec<Chromosome<P>>) {
+        population.sort_unstable_by(|a, b| b.cmp(a));
+        population.truncate(1);
+        if self.pheromone.is_empty() {
+            let best = &population[0];
+            self.bound(best);
+            for &stop in best.solution.iter() {
+                for &candidate in best.problem.neighbors.of(stop) {
+                    self.pheromone.insert(edge(stop, candidate), self.bounds.1);
+                }
+            }
+        }
+
+        let template = &population[0];
+        let ants: Vec<_> = (1..ANTS)
+            .into_par_iter()
+            .map(|_| self.construct(template, &mut thread_rng()))
+            .collect();
+        let iteration_best = ants.iter().max().cloned();
+        population.extend(ants);
+        population.sort_unstable_by(|a, b| b.cmp(a));
+
+        for pheromone in self.pheromone.values_mut() {
+            *pheromone *= 1.0 - EVAPORATION;
+        }
+        let best = population[0].clone();
+        self.bound(&best);
+        self.deposit(&best, best.score);
+        if let Some(iteration_best) = iteration_best {
+            self.deposit(&iteration_best, iteration_best.score);
+        }
+        let (min, max) = self.bounds;
+        for pheromone in self.pheromone.values_mut() {
+            *pheromone = pheromone.clamp(min, max);
+        }
+    }
+}
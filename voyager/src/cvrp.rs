@@ -0,0 +1,122 @@
+use crate::map::{self, Edit, Map, MapPoint, Metric, Stop};
+use crate::schedule::{Clock, Schedule};
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A fleet of identical vehicles, each of which can carry at most `capacity` units of the stops'
+/// demands before having to return to the depot.
+#[derive(Clone, Debug)]
+pub struct Fleet<P = MapPoint> {
+    pub capacity: u32,
+    pub max_demand: u32,
+    pub demands: HashMap<P, u32>,
+}
+
+/// The result of splitting a giant tour into vehicle routes
+#[derive(Clone, Debug)]
+pub struct Split {
+    pub cost: f64,
+    /// Each route is a range of positions into the giant tour, excluding the depot
+    pub routes: Vec<Range<usize>>,
+}
+
+impl<P: Stop> Fleet<P> {
+    /// Assigns every stop but the depot (`map[0]`) a random demand of at least one and at most
+    /// `max_demand`, which a vehicle has to be able to carry
+    pub fn random(map: &Map<P>, capacity: u32, max_demand: u32) -> Result<Self, String> {
+        if max_demand == 0 || max_demand > capacity {
+            return Err(format!(
+                "demands of up to {} can't be served by vehicles carrying {}",
+                max_demand, capacity
+            ));
+        }
+        let mut rng = thread_rng();
+        let demands = map
+            .iter()
+            .skip(1)
+            .map(|&pt| (pt, rng.gen_range(1..=max_demand)))
+            .collect();
+        Ok(Fleet {
+            capacity,
+            max_demand,
+            demands,
+        })
+    }
+
+    /// Keeps the demands in step with an edit of the map, new stops get a random demand
+    pub fn edit(&mut self, edit: Edit<P>) {
+        let max_demand = self.max_demand;
+        map::edit_table(&mut self.demands, edit, || {
+            thread_rng().gen_range(1..=max_demand)
+        });
+    }
+
+    #[inline]
+    pub fn demand(&self, point: &P) -> u32 {
+        self.demands.get(point).copied().unwrap_or_default()
+    }
+
+    /// Total demand of the stops in `route`
+    pub fn load(&self, route: &[P]) -> u32 {
+        route.iter().map(|pt| self.demand(pt)).sum()
+    }
+
+    /// Optimally splits a giant tour, which starts at the depot, into capacity-feasible routes
+    /// that preserve its order. This is Prins' split procedure, a shortest path over the DAG where
+    /// an edge (i, j) is a single vehicle serving the stops between i and j.
+    ///
+    /// With a `schedule` each route's cost also includes its lateness penalty, every vehicle
+    /// leaves the depot at time zero.
+    pub fn split(&self, tour: &[P], metric: &Metric<P>, schedule: Option<&Schedule<P>>) -> Split {
+        let depot = tour[0];
+        let len = tour.len();
+        // cost[j] is the cheapest way to serve the first j customers, pred[j] where it came from
+        let mut cost = vec![f64::INFINITY; len];
+        let mut pred = vec![0; len];
+        cost[0] = 0.0;
+
+        for i in 1..len {
+            let mut load = 0;
+            let mut length = 0.0;
+            let mut clock = schedule.map_or_else(Clock::default, |s| s.start(depot));
+            for j in i..len {
+                load += self.demand(&tour[j]);
+                if load > self.capacity {
+                    break;
+                }
+                length = if i == j {
+                    metric.distance(depot, tour[j]) + metric.distance(tour[j], depot)
+                } else {
+                    length - metric.distance(tour[j - 1], depot)
+                        + metric.distance(tour[j - 1], tour[j])
+                        + metric.distance(tour[j], depot)
+                };
+                let mut route_cost = length;
+                if let Some(schedule) = schedule {
+                    let from = if i == j { depot } else { tour[j - 1] };
+                    clock = schedule.travel(clock, metric, from, tour[j]);
+                    let back = schedule.travel(clock, metric, tour[j], depot);
+                    route_cost += schedule.strategy.weight() * back.lateness;
+                }
+                if cost[i - 1] + route_cost < cost[j] {
+                    cost[j] = cost[i - 1] + route_cost;
+                    pred[j] = i - 1;
+                }
+            }
+        }
+
+        let mut routes = Vec::new();
+        let mut end = len - 1;
+        while end > 0 {
+            routes.push((pred[end] + 1)..(end + 1));
+            end = pred[end];
+        }
+        routes.reverse();
+
+        Split {
+            cost: cost[len - 1],
+            routes,
+        }
+    }
+}
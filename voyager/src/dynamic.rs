@@ -0,0 +1,317 @@
+use crate::chromosome::{Chromosome, Problem};
+use crate::map::{self, Edit, Map, MapPoint, Stop};
+use rand::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How many generations apart the best solution gets remembered
+const MEMORY_INTERVAL: u64 = 25;
+
+/// How far a stop drifts each generation, in units of distance along each of the first two axes.
+/// Stops only ever drift across that plane.
+pub type Velocity = (f64, f64);
+
+/// Something that happens to the map, stops are found by whichever is closest to a point so
+/// that the point doesn't have to follow a stop as it drifts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change<P = MapPoint> {
+    /// A new stop appears, drifting at the given velocity
+    Add(P, Velocity),
+    Remove(P),
+    /// The stop starts drifting at the given velocity, or stops if it's zero
+    Drift(P, Velocity),
+}
+
+/// A change to the map, and the generation it happens at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Event<P = MapPoint> {
+    pub generation: u64,
+    pub change: Change<P>,
+}
+
+impl<P: Stop> FromStr for Event<P> {
+    type Err = Box<dyn std::error::Error>;
+
+    /// Parses `<generation> add x,y [dx,dy]`, `<generation> remove x,y` or
+    /// `<generation> drift x,y dx,dy`, with as many coordinates to each point as the map has
+    /// dimensions
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<_> = s.split_whitespace().collect();
+        let point = |idx: usize| -> Result<P, Self::Err> {
+            let word = words
+                .get(idx)
+                .ok_or_else(|| format!("missing a point in {:?}", s))?;
+            map::parse_point(word)
+        };
+        let velocity = |idx: usize| -> Result<Option<Velocity>, Self::Err> {
+            words.get(idx).map(|word| map::parse_pair(word)).transpose()
+        };
+
+        let generation = words
+            .first()
+            .ok_or("expected an event like <generation> <change> x,y")?
+            .parse()?;
+        let change = match words.get(1).copied() {
+            Some("add") => Change::Add(point(2)?, velocity(3)?.unwrap_or((0.0, 0.0))),
+            Some("remove") => Change::Remove(point(2)?),
+            Some("drift") => Change::Drift(
+                point(2)?,
+                velocity(3)?.ok_or_else(|| format!("missing a velocity in {:?}", s))?,
+            ),
+            _ => return Err(format!("unknown change in {:?}, use add, remove or drift", s).into()),
+        };
+        Ok(Event { generation, change })
+    }
+}
+
+/// Loads changes from a file with one event per line. Blank lines and lines starting with `#` are
+/// ignored.
+pub fn load<P: Stop>(path: &Path) -> Result<Vec<Event<P>>, Box<dyn std::error::Error>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+/// The stop in `stops` closest to `point`
+fn closest<P: Stop>(stops: &[P], point: P) -> Option<P> {
+    stops
+        .iter()
+        .copied()
+        .min_by_key(|&stop| ordered_float::OrderedFloat(stop.distance(point)))
+}
+
+/// Moves a map's stops over time, following a schedule of changes and the velocities they set
+#[derive(Clone, Debug)]
+pub struct Dynamics<P = MapPoint> {
+    /// Changes that haven't happened yet, latest first
+    pending: Vec<Event<P>>,
+    velocities: HashMap<P, Velocity>,
+    /// Opposite corners of the area stops drift in, they bounce off its edges
+    bounds: (P, P),
+    /// Stops are never removed once the map is down to this many
+    min_stops: usize,
+    memory: Memory<P>,
+    /// Share of the population replaced by random solutions each generation while the map changes
+    immigrants: f64,
+}
+
+impl<P: Stop> Dynamics<P> {
+    pub fn new(
+        mut events: Vec<Event<P>>,
+        bounds: (P, P),
+        min_stops: usize,
+        memory: usize,
+        immigrants: f64,
+    ) -> Self {
+        events.sort_by_key(|event| std::cmp::Reverse(event.generation));
+        Dynamics {
+            pending: events,
+            velocities: HashMap::new(),
+            bounds,
+            min_stops,
+            memory: Memory::new(memory),
+            immigrants,
+        }
+    }
+
+    /// Sets every one of `stops` drifting in a random direction, at up to `speed`
+    pub fn drift(&mut self, stops: &[P], speed: f64) {
+        let mut rng = thread_rng();
+        for &stop in stops {
+            let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+            let speed = rng.gen_range(0.0..=speed);
+            self.velocities
+                .insert(stop, (speed * angle.cos(), speed * angle.sin()));
+        }
+    }
+
+    /// Whether the map will never change again
+    pub fn is_static(&self) -> bool {
+        self.pending.is_empty() && self.velocities.values().all(|&v| v == (0.0, 0.0))
+    }
+
+    /// Where a stop drifts to next, bouncing off the edges and off anything `blocked`, along with
+    /// its velocity after any bounce
+    fn drifted(
+        &self,
+        stop: P,
+        (dx, dy): Velocity,
+        blocked: impl Fn(&P) -> bool,
+    ) -> Option<(P, Velocity)> {
+        let (x, y) = stop.xy();
+        let ((min_x, min_y), (max_x, max_y)) = (self.bounds.0.xy(), self.bounds.1.xy());
+        // Only heading further out of the area turns a stop around, so one that's already outside
+        // it heads back in rather than shaking in place
+        let bounces = |p: f64, dp: f64, min: f64, max: f64| {
+            if (p + dp < min && dp < 0.0) || (p + dp > max && dp > 0.0) {
+                -dp
+            } else {
+                dp
+            }
+        };
+        let (dx, dy) = (bounces(x, dx, min_x, max_x), bounces(y, dy, min_y, max_y));
+        [(dx, dy), (-dx, -dy)].iter().find_map(|&(dx, dy)| {
+            let mut coords = stop.coords();
+            coords[0] = x + dx;
+            coords[1] = y + dy;
+            let next = P::from_coords(&coords);
+            Some((next, (dx, dy))).filter(|_| !blocked(&next))
+        })
+    }
+
+    /// The edits that bring `map` to `generation`, given which stops the tour mode lets go and
+    /// where stops can't be. Returns them along with whether any stop appeared or disappeared,
+    /// rather than just drifting.
+    pub fn step(
+        &mut self,
+        generation: u64,
+        map: &Map<P>,
+        free: &[P],
+        blocked: impl Fn(&P) -> bool,
+    ) -> (Vec<Edit<P>>, bool) {
+        // Stops that were dragged or removed since the last step stop drifting
+        self.velocities.retain(|stop, _| map.contains(stop));
+
+        let mut edits = Vec::new();
+        let mut free = free.to_vec();
+        // Where there are stops once the edits so far are made, so no two end up in one place
+        let mut occupied: HashSet<P> = map.iter().copied().collect();
+        while let Some(event) = self.pending.last().filter(|e| e.generation <= generation) {
+            match event.change {
+                Change::Add(point, velocity) if !blocked(&point) && occupied.insert(point) => {
+                    edits.push(Edit::Add(point));
+                    self.velocities.insert(point, velocity);
+                    free.push(point);
+                }
+                Change::Remove(point) if occupied.len() > self.min_stops => {
+                    if let Some(stop) = closest(&free, point) {
+                        edits.push(Edit::Remove(stop));
+                        self.velocities.remove(&stop);
+                        free.retain(|&pt| pt != stop);
+                        occupied.remove(&stop);
+                    }
+                }
+                Change::Drift(point, velocity) => {
+                    // Stops added by this step aren't on the map yet
+                    let stops: Vec<_> = map.iter().chain(&free).copied().collect();
+                    if let Some(stop) = closest(&stops, point) {
+                        self.velocities.insert(stop, velocity);
+                    }
+                }
+                _ => {}
+            }
+            self.pending.pop();
+        }
+        let changed = !edits.is_empty();
+
+        let moving: Vec<_> = self
+            .velocities
+            .iter()
+            .filter(|(_, &v)| v != (0.0, 0.0))
+            .map(|(&stop, &v)| (stop, v))
+            .collect();
+        for (stop, velocity) in moving {
+            let next = self.drifted(stop, velocity, |pt| blocked(pt) || occupied.contains(pt));
+            if let Some((to, velocity)) = next {
+                occupied.remove(&stop);
+                occupied.insert(to);
+                edits.push(Edit::Move { from: stop, to });
+                self.velocities.remove(&stop);
+                self.velocities.insert(to, velocity);
+            }
+        }
+        (edits, changed)
+    }
+
+    /// Keeps the remembered solutions in step with edits of the map, or with a change to the
+    /// problem if there are none
+    pub fn edit(&mut self, edits: &[Edit<P>], problem: &Arc<Problem<P>>) {
+        self.memory.edit(edits, problem);
+    }
+
+    /// Helps `population`, which is sorted best first, follow the map: remembered solutions come
+    /// back when stops appear or disappear, random immigrants keep it diverse while the map keeps
+    /// changing, and every so often its best solution is remembered
+    pub fn adapt(
+        &mut self,
+        generation: u64,
+        changed: bool,
+        population: &mut [Chromosome<P>],
+        map: &Map<P>,
+        problem: &Arc<Problem<P>>,
+    ) {
+        if changed {
+            self.memory.recall(population);
+        }
+        if !self.is_static() {
+            immigrants(population, self.immigrants, map, problem);
+        }
+        self.memory.store(generation, &population[0]);
+    }
+}
+
+/// Good solutions from earlier on, which are put back into the population when the map changes
+/// in case the optimum returns to somewhere near where it was
+#[derive(Clone, Debug, Default)]
+struct Memory<P> {
+    capacity: usize,
+    entries: Vec<Chromosome<P>>,
+}
+
+impl<P: Stop> Memory<P> {
+    fn new(capacity: usize) -> Self {
+        Memory {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Remembers `best` if it's been long enough since the last time, forgetting the oldest entry
+    /// to make room
+    fn store(&mut self, generation: u64, best: &Chromosome<P>) {
+        if self.capacity == 0 || !generation.is_multiple_of(MEMORY_INTERVAL) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push(best.clone());
+    }
+
+    /// Adapts the remembered solutions to edits of the map
+    fn edit(&mut self, edits: &[Edit<P>], problem: &Arc<Problem<P>>) {
+        self.entries = self
+            .entries
+            .drain(..)
+            .map(|c| c.edited(edits, problem.clone()))
+            .collect();
+    }
+
+    /// Puts the remembered solutions in place of the worst of `population`, which is sorted best
+    /// first
+    fn recall(&self, population: &mut [Chromosome<P>]) {
+        let count = self.entries.len().min(population.len());
+        let start = population.len() - count;
+        population[start..].clone_from_slice(&self.entries[..count]);
+    }
+}
+
+/// Puts random solutions in place of the worst `rate` of `population`, which is sorted best first,
+/// so it doesn't lose the diversity it needs to follow a changing map. The best one always stays.
+fn immigrants<P: Stop>(
+    population: &mut [Chromosome<P>],
+    rate: f64,
+    map: &Map<P>,
+    problem: &Arc<Problem<P>>,
+) {
+    let count = (population.len() as f64 * rate).round() as usize;
+    let start = population.len().saturating_sub(count).max(1);
+    for chromosome in &mut population[start..] {
+        *chromosome = Chromosome::random(map, problem);
+    }
+}
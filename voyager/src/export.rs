@@ -0,0 +1,269 @@
+use crate::chromosome::Chromosome;
+use crate::map::{Metric, Stop};
+use crate::render::{self, Scene, Surface, Viewport};
+use crate::tour;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, Rgba, RgbaImage,
+};
+use sdl2::{
+    pixels::Color,
+    rect::{Point, Rect},
+};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Size of exported images and recorded frames
+pub const EXPORT_WIDTH: u32 = 600;
+pub const EXPORT_HEIGHT: u32 = 800;
+/// How long each recorded frame is shown for
+const FRAME_DELAY_MS: u32 = 100;
+/// Trades the quality of a GIF's palette for how fast frames are encoded, from 1 to 30
+const GIF_SPEED: i32 = 10;
+
+fn rgb(color: Color) -> String {
+    format!("rgb({},{},{})", color.r, color.g, color.b)
+}
+
+fn opacity(color: Color) -> f64 {
+    f64::from(color.a) / 255.0
+}
+
+/// Collects drawing commands as SVG elements
+pub struct Svg {
+    width: u32,
+    height: u32,
+    color: Color,
+    elements: String,
+}
+
+impl Svg {
+    pub fn new(width: u32, height: u32) -> Self {
+        Svg {
+            width,
+            height,
+            color: Color::BLACK,
+            elements: String::new(),
+        }
+    }
+
+    pub fn finish(self) -> String {
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\">\n{}</svg>\n",
+            self.elements,
+            w = self.width,
+            h = self.height,
+        )
+    }
+}
+
+impl Surface for Svg {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    fn clear(&mut self) {
+        self.elements.clear();
+        let rect = Rect::new(0, 0, self.width, self.height);
+        self.fill_rects(&[rect]).unwrap();
+    }
+
+    fn fill_rects(&mut self, rects: &[Rect]) -> Result<(), String> {
+        let (fill, opacity) = (rgb(self.color), opacity(self.color));
+        for rect in rects {
+            writeln!(
+                self.elements,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" \
+                 fill-opacity=\"{:.3}\"/>",
+                rect.x(),
+                rect.y(),
+                rect.width(),
+                rect.height(),
+                fill,
+                opacity
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String> {
+        let points: Vec<_> = points
+            .iter()
+            .map(|pt| format!("{},{}", pt.x(), pt.y()))
+            .collect();
+        writeln!(
+            self.elements,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-opacity=\"{:.3}\"/>",
+            points.join(" "),
+            rgb(self.color),
+            opacity(self.color)
+        )
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// Rasterizes drawing commands into an image, blending translucent colors like the window does
+pub struct Raster {
+    pub image: RgbaImage,
+    color: Color,
+}
+
+impl Raster {
+    pub fn new(width: u32, height: u32) -> Self {
+        Raster {
+            image: RgbaImage::new(width, height),
+            color: Color::BLACK,
+        }
+    }
+
+    fn plot(&mut self, x: i32, y: i32) {
+        if x < 0 || y < 0 || x >= self.image.width() as i32 || y >= self.image.height() as i32 {
+            return;
+        }
+        let pixel = self.image.get_pixel_mut(x as u32, y as u32);
+        let alpha = u32::from(self.color.a);
+        let blend = |src: u8, dst: u8| {
+            ((u32::from(src) * alpha + u32::from(dst) * (255 - alpha)) / 255) as u8
+        };
+        let Rgba([r, g, b, a]) = *pixel;
+        *pixel = Rgba([
+            blend(self.color.r, r),
+            blend(self.color.g, g),
+            blend(self.color.b, b),
+            a.max(self.color.a),
+        ]);
+    }
+
+    /// Bresenham's line, including its end but only including its start if `start` is set
+    fn line(&mut self, from: Point, to: Point, start: bool) {
+        let (mut x, mut y) = (from.x(), from.y());
+        let (dx, dy) = ((to.x() - x).abs(), -(to.y() - y).abs());
+        let (sx, sy) = ((to.x() - x).signum(), (to.y() - y).signum());
+        let mut err = dx + dy;
+        loop {
+            if start || (x, y) != (from.x(), from.y()) {
+                self.plot(x, y);
+            }
+            if (x, y) == (to.x(), to.y()) {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+}
+
+impl Surface for Raster {
+    fn size(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    fn clear(&mut self) {
+        let Color { r, g, b, a } = self.color;
+        self.image
+            .pixels_mut()
+            .for_each(|px| *px = Rgba([r, g, b, a]));
+    }
+
+    fn fill_rects(&mut self, rects: &[Rect]) -> Result<(), String> {
+        for rect in rects {
+            for y in rect.top()..rect.bottom() {
+                for x in rect.left()..rect.right() {
+                    self.plot(x, y);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String> {
+        for (idx, pair) in points.windows(2).enumerate() {
+            // Only the first segment draws its start, shared ends would be blended twice otherwise
+            self.line(pair[0], pair[1], idx == 0);
+        }
+        Ok(())
+    }
+}
+
+/// A view of the whole map of `chromosome`, at the size of exported images. Geographic maps are
+/// shown north up.
+fn viewport<P: Stop>(chromosome: &Chromosome<P>) -> Viewport {
+    let mut viewport = Viewport::new(EXPORT_WIDTH, EXPORT_HEIGHT);
+    viewport.north_up = matches!(chromosome.problem.metric, Metric::Haversine);
+    viewport.fit(chromosome.solution.iter());
+    viewport
+}
+
+/// Writes `scene` to `path` as an SVG or a PNG depending on its extension, or the stops of its
+/// best tour as a CSV, JSON, text or GeoJSON file
+pub fn save<P: Stop>(path: &Path, scene: &Scene<P>) -> Result<(), Box<dyn Error>> {
+    let viewport = viewport(&scene.population[0]);
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("svg") => {
+            let mut svg = Svg::new(EXPORT_WIDTH, EXPORT_HEIGHT);
+            render::paint(&mut svg, viewport, scene)?;
+            std::fs::write(path, svg.finish())?;
+        }
+        Some("png") => {
+            let mut raster = Raster::new(EXPORT_WIDTH, EXPORT_HEIGHT);
+            render::paint(&mut raster, viewport, scene)?;
+            raster.image.save(path)?;
+        }
+        Some("csv") | Some("json") | Some("txt") | Some("geojson") => {
+            tour::save(path, &scene.population[0])?
+        }
+        _ => {
+            let message = format!(
+                "can't export to {}, use .svg, .png, .csv, .json, .txt or .geojson",
+                path.display()
+            );
+            return Err(message.into());
+        }
+    }
+    Ok(())
+}
+
+/// Writes scenes to an animated GIF as they're recorded
+pub struct Recorder {
+    encoder: GifEncoder<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut encoder =
+            GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), GIF_SPEED);
+        encoder.set_repeat(Repeat::Infinite)?;
+        Ok(Recorder { encoder })
+    }
+
+    pub fn record<P: Stop>(&mut self, scene: &Scene<P>) -> Result<(), Box<dyn Error>> {
+        let mut raster = Raster::new(EXPORT_WIDTH, EXPORT_HEIGHT);
+        let viewport = viewport(&scene.population[0]);
+        render::paint(&mut raster, viewport, scene)?;
+        let delay = Delay::from_numer_denom_ms(FRAME_DELAY_MS, 1);
+        self.encoder
+            .encode_frame(Frame::from_parts(raster.image, 0, 0, delay))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use crate::render::Surface;
+use sdl2::rect::Rect;
+
+/// Width and height of a glyph, in font pixels
+pub const GLYPH_WIDTH: i32 = 3;
+pub const GLYPH_HEIGHT: i32 = 5;
+
+/// A tiny bitmap font, so text can be drawn without SDL_ttf and a font file. Each glyph is five
+/// rows of three bits, most significant bit on the left.
+#[rustfmt::skip]
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('|', [0b010, 0b010, 0b010, 0b010, 0b010]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+];
+
+fn glyph(c: char) -> Option<&'static [u8; 5]> {
+    let c = c.to_ascii_uppercase();
+    GLYPHS
+        .iter()
+        .find(|(glyph, _)| *glyph == c)
+        .map(|(_, rows)| rows)
+}
+
+/// Width in screen pixels of `text` drawn at `scale`
+pub fn text_width(text: &str, scale: i32) -> i32 {
+    let len = text.chars().count() as i32;
+    (len * (GLYPH_WIDTH + 1) - 1).max(0) * scale
+}
+
+/// Draws `text` with its top left corner at (x, y), each font pixel `scale` screen pixels wide.
+/// Lowercase letters are drawn as uppercase ones, and anything the font lacks as a space.
+pub fn draw_text(
+    surface: &mut impl Surface,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: i32,
+) -> Result<(), String> {
+    let mut pixels = Vec::new();
+    for (idx, c) in text.chars().enumerate() {
+        let left = x + idx as i32 * (GLYPH_WIDTH + 1) * scale;
+        let rows = match glyph(c) {
+            Some(rows) => rows,
+            None => continue,
+        };
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                    pixels.push(Rect::new(
+                        left + col * scale,
+                        y + row as i32 * scale,
+                        scale as u32,
+                        scale as u32,
+                    ));
+                }
+            }
+        }
+    }
+    surface.fill_rects(&pixels)
+}
@@ -0,0 +1,212 @@
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::iter::once;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// How a solution came to be
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// Made from scratch, at random, greedily or as it was loaded
+    Seed,
+    /// Bred from two parents by order crossover
+    OrderCrossover,
+    /// Polished from its parent with Or-opt and Lin-Kernighan moves
+    Polish,
+}
+
+impl Origin {
+    pub fn name(self) -> &'static str {
+        match self {
+            Origin::Seed => "seed",
+            Origin::OrderCrossover => "ox",
+            Origin::Polish => "polish",
+        }
+    }
+}
+
+/// A mutation operator of the GA
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    RandomSwap,
+    NearestNeighbor,
+    TwoOpt,
+}
+
+impl Mutation {
+    pub const ALL: [Mutation; 3] = [
+        Mutation::RandomSwap,
+        Mutation::NearestNeighbor,
+        Mutation::TwoOpt,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mutation::RandomSwap => "random_swap",
+            Mutation::NearestNeighbor => "nearest_neighbor",
+            Mutation::TwoOpt => "two_opt",
+        }
+    }
+}
+
+/// What came of a mutation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// It improved the solution, and was kept
+    Improved,
+    /// It made the solution worse, but was kept anyway
+    Worse,
+    /// It made the solution worse, and was undone
+    Rejected,
+}
+
+impl Outcome {
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Improved => "improved",
+            Outcome::Worse => "worse",
+            Outcome::Rejected => "rejected",
+        }
+    }
+}
+
+/// Where a solution comes from. Each one holds on to its parents', so the ancestry of the living
+/// solutions stays around and the rest of it is dropped.
+#[derive(Debug)]
+pub struct Birth {
+    pub id: u64,
+    /// The generation it was born in
+    pub generation: u64,
+    pub origin: Origin,
+    pub parents: Vec<Arc<Birth>>,
+    /// The mutations tried on it once it was born, in order, and what came of each
+    pub mutations: Vec<(Mutation, Outcome)>,
+}
+
+impl Drop for Birth {
+    /// Drops the ancestors no one else holds on to one at a time, as recursing through thousands
+    /// of generations of them could overflow the stack
+    fn drop(&mut self) {
+        let mut ancestors = std::mem::take(&mut self.parents);
+        while let Some(ancestor) = ancestors.pop() {
+            if let Ok(mut ancestor) = Arc::try_unwrap(ancestor) {
+                ancestors.append(&mut ancestor.parents);
+            }
+        }
+    }
+}
+
+/// Hands out the births of solutions, when a problem keeps track of their genealogy
+#[derive(Debug, Default)]
+pub struct Genealogy {
+    ids: AtomicU64,
+    /// The generation being bred
+    generation: AtomicU64,
+}
+
+impl Genealogy {
+    pub fn set_generation(&self, generation: u64) {
+        self.generation.store(generation, Ordering::Relaxed);
+    }
+
+    /// The birth of a new solution from `parents`, in the generation being bred
+    pub fn birth(&self, origin: Origin, parents: Vec<Arc<Birth>>) -> Arc<Birth> {
+        Arc::new(Birth {
+            id: self.ids.fetch_add(1, Ordering::Relaxed),
+            generation: self.generation.load(Ordering::Relaxed),
+            origin,
+            parents,
+            mutations: Vec::new(),
+        })
+    }
+}
+
+/// `birth` and all of its ancestors, each once, oldest first
+pub fn lineage(birth: &Birth) -> Vec<&Birth> {
+    let mut lineage = vec![birth];
+    let mut seen: HashSet<u64> = once(birth.id).collect();
+    let mut idx = 0;
+    while idx < lineage.len() {
+        for parent in &lineage[idx].parents {
+            if seen.insert(parent.id) {
+                lineage.push(parent);
+            }
+        }
+        idx += 1;
+    }
+    lineage.sort_by_key(|birth| birth.id);
+    lineage
+}
+
+/// The lineage of `best` as a Graphviz graph, with an arrow from each parent to its child
+pub fn dot(best: &Birth) -> String {
+    let mut dot = String::from("digraph lineage {\n    node [shape=box];\n");
+    for birth in lineage(best) {
+        let mut label = format!(
+            "#{} gen {}\\n{}",
+            birth.id,
+            birth.generation,
+            birth.origin.name()
+        );
+        for (mutation, outcome) in &birth.mutations {
+            let _ = write!(label, "\\n{} {}", mutation.name(), outcome.name());
+        }
+        let style = if birth.id == best.id {
+            ", style=bold"
+        } else {
+            ""
+        };
+        let _ = writeln!(dot, "    {} [label=\"{}\"{}];", birth.id, label, style);
+        for parent in &birth.parents {
+            let _ = writeln!(dot, "    {} -> {};", parent.id, birth.id);
+        }
+    }
+    dot += "}\n";
+    dot
+}
+
+/// The lineage of `best` as JSON, listing each solution in it with the ids of its parents
+pub fn json(best: &Birth) -> Value {
+    let solutions: Vec<Value> = lineage(best)
+        .into_iter()
+        .map(|birth| {
+            let parents: Vec<u64> = birth.parents.iter().map(|parent| parent.id).collect();
+            let mutations: Vec<Value> = birth
+                .mutations
+                .iter()
+                .map(|(mutation, outcome)| {
+                    json!({ "operator": mutation.name(), "outcome": outcome.name() })
+                })
+                .collect();
+            json!({
+                "id": birth.id,
+                "generation": birth.generation,
+                "origin": birth.origin.name(),
+                "parents": parents,
+                "mutations": mutations,
+            })
+        })
+        .collect();
+    json!({ "best": best.id, "solutions": solutions })
+}
+
+/// Saves the lineage of `best` as a DOT graph or as JSON, depending on the extension of `path`
+pub fn save(path: &Path, best: &Birth) -> Result<(), Box<dyn Error>> {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let contents = match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("dot") | Some("gv") => dot(best),
+        Some("json") => json(best).to_string(),
+        _ => {
+            let message = format!(
+                "can't save a lineage to {}, use .dot or .json",
+                path.display()
+            );
+            return Err(message.into());
+        }
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::chromosome::Chromosome;
+use crate::map::{Stop, StopIds};
+use serde_json::{json, Map as Object, Value};
+use std::error::Error;
+
+/// Mean radius of the Earth, in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Distance in meters along the Earth's surface between two points given as longitude and
+/// latitude in degrees
+pub fn haversine((lon1, lat1): (f64, f64), (lon2, lat2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let (dlat, dlon) = (lat2 - lat1, (lon2 - lon1).to_radians());
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+/// A feature's id, taken from its `property` if it has one and from its own id otherwise
+fn id(feature: &Value, property: Option<&str>) -> Option<String> {
+    let id = match property {
+        Some(property) => feature.get("properties")?.get(property)?,
+        None => feature.get("id")?,
+    };
+    match id {
+        Value::String(id) => Some(id.clone()),
+        Value::Null => None,
+        id => Some(id.to_string()),
+    }
+}
+
+/// Parses a FeatureCollection of Points into stops, along with their ids. Only the longitude and
+/// latitude of each point are kept, any altitude is dropped.
+pub fn parse<P: Stop>(
+    geojson: &str,
+    property: Option<&str>,
+) -> Result<(Vec<P>, StopIds<P>), Box<dyn Error>> {
+    let collection: Value = serde_json::from_str(geojson)?;
+    if collection["type"] != "FeatureCollection" {
+        return Err("expected a GeoJSON FeatureCollection".into());
+    }
+    let features = collection["features"]
+        .as_array()
+        .ok_or("expected the FeatureCollection to have an array of features")?;
+    let mut stops = Vec::with_capacity(features.len());
+    let mut ids = StopIds::new();
+    for feature in features {
+        let geometry = &feature["geometry"];
+        if geometry["type"] != "Point" {
+            return Err(format!("expected only Point features, got {}", geometry).into());
+        }
+        let coords = geometry["coordinates"]
+            .as_array()
+            .and_then(|coords| coords.iter().map(Value::as_f64).collect::<Option<Vec<_>>>())
+            .filter(|coords| coords.len() >= 2)
+            .ok_or_else(|| format!("expected a point like [lon, lat], got {}", geometry))?;
+        let stop = P::from_coords(&coords[..2]);
+        stops.push(stop);
+        if let Some(id) = id(feature, property) {
+            ids.insert(stop, id);
+        }
+    }
+    Ok((stops, ids))
+}
+
+/// A FeatureCollection with a LineString for each route of the tour, through its stops in the
+/// order they're visited. Each one has its length and the length of each of its legs as
+/// properties, and the ids of its stops where they're known.
+pub fn feature_collection<P: Stop>(chromosome: &Chromosome<P>) -> Value {
+    let metric = &chromosome.problem.metric;
+    let features: Vec<_> = chromosome
+        .tours()
+        .iter()
+        .map(|tour| {
+            let coordinates: Vec<_> = tour.iter().map(|stop| stop.coords()).collect();
+            let legs: Vec<_> = tour
+                .windows(2)
+                .map(|leg| metric.distance(leg[0], leg[1]))
+                .collect();
+            let stops: Vec<_> = tour
+                .iter()
+                .map(|stop| chromosome.problem.ids.get(stop))
+                .collect();
+            let mut properties = Object::new();
+            properties.insert("length".to_string(), json!(legs.iter().sum::<f64>()));
+            properties.insert("legs".to_string(), json!(legs));
+            if stops.iter().any(Option::is_some) {
+                properties.insert("stops".to_string(), json!(stops));
+            }
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "LineString", "coordinates": coordinates },
+                "properties": properties,
+            })
+        })
+        .collect();
+    json!({ "type": "FeatureCollection", "features": features })
+}
@@ -0,0 +1,320 @@
+use crate::chromosome::{Problem, TourMode};
+use crate::map::Stop;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+
+/// Most 2-opt moves a Lin-Kernighan move chains together, so it exchanges up to one more edge
+/// than this
+const LK_DEPTH: usize = 4;
+/// Longest stretch of consecutive stops Or-opt moves at once
+const OR_OPT_SEGMENT: usize = 3;
+/// Smallest gain worth making a move for, so rounding errors can't make the search cycle
+const MIN_GAIN: f64 = 1e-9;
+
+/// What joins the ends of a tour that doesn't return to its start, so it can be searched as a
+/// cycle like the others
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dummy {
+    /// The tour returns to its start, there's nothing to join
+    None,
+    /// A node at no distance from any stop, wherever it goes the tour is open there
+    Open,
+    /// A node joining the end depot to the start depot, which is infinitely far from any other
+    /// stop so it never moves from between them
+    Bridge { start: usize, end: usize },
+}
+
+/// A tour as a cycle of nodes, which are indices into its stops, with the node at each position
+/// and the position of each node
+struct Cycle {
+    nodes: Vec<usize>,
+    position: Vec<usize>,
+}
+
+impl Cycle {
+    fn new(len: usize) -> Self {
+        Cycle {
+            nodes: (0..len).collect(),
+            position: (0..len).collect(),
+        }
+    }
+
+    fn succ(&self, node: usize) -> usize {
+        self.nodes[(self.position[node] + 1) % self.nodes.len()]
+    }
+
+    fn pred(&self, node: usize) -> usize {
+        let len = self.nodes.len();
+        self.nodes[(self.position[node] + len - 1) % len]
+    }
+
+    /// The node after `node` going forward, or going backward if `forward` isn't set
+    fn next(&self, node: usize, forward: bool) -> usize {
+        if forward {
+            self.succ(node)
+        } else {
+            self.pred(node)
+        }
+    }
+
+    /// Reverses the path from `from` to `to`, both included, going forward or backward
+    fn reverse(&mut self, from: usize, to: usize, forward: bool) {
+        let (from, to) = if forward { (from, to) } else { (to, from) };
+        let len = self.nodes.len();
+        let (mut i, mut j) = (self.position[from], self.position[to]);
+        for _ in 0..((j + len - i) % len).div_ceil(2) {
+            self.nodes.swap(i, j);
+            self.position[self.nodes[i]] = i;
+            self.position[self.nodes[j]] = j;
+            i = (i + 1) % len;
+            j = (j + len - 1) % len;
+        }
+    }
+
+    /// Moves the path from `first` to `last` going forward between `after` and its successor,
+    /// backwards if `reversed` is set
+    fn move_segment(&mut self, first: usize, last: usize, after: usize, reversed: bool) {
+        let mut segment = vec![first];
+        while segment[segment.len() - 1] != last {
+            segment.push(self.succ(segment[segment.len() - 1]));
+        }
+        if reversed {
+            segment.reverse();
+        }
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        let mut node = self.succ(last);
+        while node != first {
+            nodes.push(node);
+            if node == after {
+                nodes.extend_from_slice(&segment);
+            }
+            node = self.succ(node);
+        }
+        for (idx, &node) in nodes.iter().enumerate() {
+            self.position[node] = idx;
+        }
+        self.nodes = nodes;
+    }
+}
+
+/// A tour's stops, and what the search needs to know about them
+struct Instance<'a, P> {
+    stops: &'a [P],
+    problem: &'a Problem<P>,
+    /// The candidates of each stop, as nodes
+    candidates: Vec<Vec<usize>>,
+    dummy: Dummy,
+    /// How many distances between stops the search has measured
+    measured: Cell<u64>,
+}
+
+impl<'a, P: Stop> Instance<'a, P> {
+    fn new(stops: &'a [P], problem: &'a Problem<P>) -> Self {
+        let index: HashMap<P, usize> = stops
+            .iter()
+            .enumerate()
+            .map(|(idx, &stop)| (stop, idx))
+            .collect();
+        let candidates = stops
+            .iter()
+            .map(|&stop| {
+                let candidates = problem.neighbors.of(stop).iter();
+                candidates.filter_map(|c| index.get(c).copied()).collect()
+            })
+            .collect();
+        let dummy = match problem.mode {
+            TourMode::Closed | TourMode::Depot { end: false } => Dummy::None,
+            TourMode::Open => Dummy::Open,
+            TourMode::Depot { end: true } => Dummy::Bridge {
+                start: 0,
+                end: stops.len() - 1,
+            },
+        };
+        Instance {
+            stops,
+            problem,
+            candidates,
+            dummy,
+            measured: Cell::new(0),
+        }
+    }
+
+    /// How many nodes the cycle has, the dummy included
+    fn len(&self) -> usize {
+        self.stops.len() + usize::from(self.dummy != Dummy::None)
+    }
+
+    fn distance(&self, a: usize, b: usize) -> f64 {
+        let dummy = self.stops.len();
+        if a != dummy && b != dummy {
+            self.measured.set(self.measured.get() + 1);
+            return self.problem.metric.distance(self.stops[a], self.stops[b]);
+        }
+        let other = if a == dummy { b } else { a };
+        match self.dummy {
+            Dummy::Bridge { start, end } if other != start && other != end => f64::INFINITY,
+            _ => 0.0,
+        }
+    }
+
+    fn candidates(&self, node: usize) -> &[usize] {
+        self.candidates.get(node).map_or(&[], Vec::as_slice)
+    }
+
+    /// Looks for a chain of 2-opt moves that shortens the tour, starting by breaking the edge
+    /// from `t1` to the next node going forward or backward. Each move adds an edge from the end
+    /// of the last one to a candidate, and the chain goes on while what it removed outweighs what
+    /// it added. The best prefix of the chain is kept, returning the nodes whose edges changed.
+    fn lin_kernighan(&self, cycle: &mut Cycle, t1: usize, forward: bool) -> Option<Vec<usize>> {
+        let mut t2 = cycle.next(t1, forward);
+        // Length removed minus length added so far, leaving out the edge that closes the tour
+        let mut gain = self.distance(t1, t2);
+        let mut added: Vec<(usize, usize)> = Vec::with_capacity(LK_DEPTH);
+        // The paths reversed so far, as they need to be reversed again to undo them
+        let mut reversed: Vec<(usize, usize)> = Vec::with_capacity(LK_DEPTH);
+        let mut touched = vec![t1, t2];
+        let (mut best_gain, mut best_len) = (MIN_GAIN, 0);
+
+        for _ in 0..LK_DEPTH {
+            let succ = cycle.next(t2, forward);
+            let choice = self
+                .candidates(t2)
+                .iter()
+                .copied()
+                .filter(|&t3| t3 != t1 && t3 != succ && gain - self.distance(t2, t3) > 0.0)
+                .map(|t3| (t3, cycle.next(t3, !forward)))
+                .filter(|&(t3, t4)| !added.contains(&(t3, t4)) && !added.contains(&(t4, t3)))
+                .max_by(|&(a3, a4), &(b3, b4)| {
+                    let value = |t3, t4| self.distance(t4, t3) - self.distance(t2, t3);
+                    value(a3, a4).total_cmp(&value(b3, b4))
+                });
+            let (t3, t4) = match choice {
+                Some(choice) => choice,
+                None => break,
+            };
+            // Breaking t4-t3 and adding t2-t3 turns the path from t2 to t4 around
+            cycle.reverse(t2, t4, forward);
+            reversed.push((t4, t2));
+            added.push((t2, t3));
+            touched.extend_from_slice(&[t3, t4]);
+            gain += self.distance(t4, t3) - self.distance(t2, t3);
+            let closed = gain - self.distance(t4, t1);
+            if closed > best_gain {
+                best_gain = closed;
+                best_len = reversed.len();
+            }
+            t2 = t4;
+        }
+
+        for &(from, to) in reversed[best_len..].iter().rev() {
+            cycle.reverse(from, to, forward);
+        }
+        Some(touched).filter(|_| best_len > 0)
+    }
+
+    /// Looks for somewhere to move the few stops starting at `first` going forward that shortens
+    /// the tour, next to a candidate of either end and either way around, and moves them to the
+    /// best place found. Returns the nodes whose edges changed.
+    fn or_opt(&self, cycle: &mut Cycle, first: usize) -> Option<Vec<usize>> {
+        let dummy = self.stops.len();
+        let mut segment = vec![first];
+        let mut best: Option<(f64, usize, usize, bool)> = None;
+        while segment.len() <= OR_OPT_SEGMENT {
+            let last = segment[segment.len() - 1];
+            let (before, after) = (cycle.pred(first), cycle.succ(last));
+            if segment.contains(&dummy) || before == last || after == first {
+                break;
+            }
+            let removed = self.distance(before, first) + self.distance(last, after)
+                - self.distance(before, after);
+            for &c in self.candidates(first).iter().chain(self.candidates(last)) {
+                for (x, y) in [(cycle.pred(c), c), (c, cycle.succ(c))] {
+                    if segment.contains(&x) || segment.contains(&y) {
+                        continue;
+                    }
+                    let inserted = self.distance(x, first) + self.distance(last, y);
+                    let flipped = self.distance(x, last) + self.distance(first, y);
+                    let (added, reversed) = if flipped < inserted {
+                        (flipped, true)
+                    } else {
+                        (inserted, false)
+                    };
+                    let gain = removed - (added - self.distance(x, y));
+                    if gain > best.map_or(MIN_GAIN, |(best, ..)| best) {
+                        best = Some((gain, last, x, reversed));
+                    }
+                }
+            }
+            segment.push(after);
+        }
+
+        let (_, last, x, reversed) = best?;
+        let touched = vec![
+            cycle.pred(first),
+            first,
+            last,
+            cycle.succ(last),
+            x,
+            cycle.succ(x),
+        ];
+        cycle.move_segment(first, last, x, reversed);
+        Some(touched)
+    }
+
+    /// The tour's stops in the order the cycle visits them, laid out the way the tour mode
+    /// expects
+    fn tour(&self, cycle: &Cycle) -> Vec<P> {
+        let dummy = self.stops.len();
+        let (start, forward) = match self.dummy {
+            Dummy::None => (0, true),
+            Dummy::Open => (cycle.succ(dummy), true),
+            Dummy::Bridge { start, .. } => (start, cycle.pred(start) == dummy),
+        };
+        let mut tour = Vec::with_capacity(self.stops.len());
+        let mut node = start;
+        while tour.len() < self.stops.len() {
+            if node != dummy {
+                tour.push(self.stops[node]);
+            }
+            node = cycle.next(node, forward);
+        }
+        tour
+    }
+}
+
+/// Shortens `tour` with Or-opt and Lin-Kernighan moves until neither finds anything, keeping
+/// whichever stops the tour mode pins in place. Stops whose edges haven't changed since they last
+/// failed to improve the tour aren't tried again, which are their don't-look bits. The distances
+/// it measures count towards the problem's evaluations. Returns whether the tour changed.
+pub fn optimize<P: Stop>(tour: &mut [P], problem: &Problem<P>) -> bool {
+    if tour.len() < 5 {
+        return false;
+    }
+    let stops = tour.to_vec();
+    let instance = Instance::new(&stops, problem);
+    let mut cycle = Cycle::new(instance.len());
+    let mut queue: VecDeque<usize> = (0..stops.len()).collect();
+    let mut queued = vec![true; instance.len()];
+    let mut improved = false;
+    while let Some(t1) = queue.pop_front() {
+        queued[t1] = false;
+        let touched = instance
+            .lin_kernighan(&mut cycle, t1, true)
+            .or_else(|| instance.lin_kernighan(&mut cycle, t1, false))
+            .or_else(|| instance.or_opt(&mut cycle, t1));
+        if let Some(touched) = touched {
+            improved = true;
+            for node in touched {
+                if node < stops.len() && !queued[node] {
+                    queued[node] = true;
+                    queue.push_back(node);
+                }
+            }
+        }
+    }
+    problem.measured(instance.measured.get(), stops.len());
+    if improved {
+        tour.copy_from_slice(&instance.tour(&cycle));
+    }
+    improved
+}
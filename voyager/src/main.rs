@@ -1,14 +1,64 @@
+mod annealing;
 mod chromosome;
+mod colony;
+mod cvrp;
+mod dynamic;
+mod export;
+mod font;
+mod genealogy;
+mod geo;
+mod local;
 mod map;
+mod orienteering;
+mod pareto;
+mod render;
+mod schedule;
+mod solver;
+mod spatial;
+mod stats;
+mod steady;
+mod tabu;
+mod terrain;
+mod tour;
 
-use chromosome::Chromosome;
+use chromosome::{Chromosome, Problem, TourMode};
+use cvrp::Fleet;
+use dynamic::{Dynamics, Event as Change};
+use export::Recorder;
 use indicatif::{ProgressBar, ProgressStyle};
-use itertools::Itertools;
-use rand::{distributions::WeightedIndex, prelude::*};
+use map::{
+    Coordinate, Edit, Generator, Map, MapInner, MapPoint, MapUnit, Metric, Point, Precision, Stop,
+    StopIds,
+};
+use ordered_float::OrderedFloat;
+use orienteering::Orienteering;
+use pareto::Objective;
+use rand::{
+    distributions::{WeightedError, WeightedIndex},
+    prelude::*,
+    rngs::StdRng,
+};
 use rayon::prelude::*;
-use sdl2::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect};
+use render::{Hud, Renderer, Scene, View};
+use schedule::{Schedule, Strategy};
+use sdl2::{event::Event, keyboard::Keycode, mouse::MouseButton};
+use solver::{Search, Solver};
+use spatial::Neighbors;
+use stats::Stats;
+use std::iter::once;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use steady::{Replacement, SteadyState};
+use structopt::StructOpt;
+use terrain::{Polygon, Terrain};
 
 const TSP_STOPS: usize = 150;
+/// Stops can't be removed once the map is down to this many
+const MIN_STOPS: usize = 4;
+const MAX_DEMAND: u32 = 10;
+const MAX_REWARD: u32 = 10;
 const GENERATION_SIZE: usize = 256;
 const PARENT_SURVIVAL_RATE: f64 = 0.1;
 const PARENTS_SUVIVE: usize = GENERATION_SIZE / (PARENT_SURVIVAL_RATE * 100.0) as usize;
@@ -18,36 +68,797 @@ const GRID_WIDTH: i32 = 300;
 const GRID_HEIGHT: i32 = 400;
 const WINDOW_WIDTH: i32 = GRID_WIDTH * GRID_CELL_SIZE + GRID_CELL_SIZE;
 const WINDOW_HEIGHT: i32 = GRID_HEIGHT * GRID_CELL_SIZE + GRID_CELL_SIZE;
-const COLOR_BACKGROUND: Color = Color::RGBA(10, 14, 20, 255);
-const COLOR_ENTITY: Color = Color::RGBA(230, 180, 80, 255);
-const COLOR_PATH: Color = Color::RGBA(89, 194, 255, 255);
+/// Size of the area stops are placed in
+const MAP_WIDTH: u32 = (WINDOW_WIDTH - GRID_CELL_SIZE) as u32;
+const MAP_HEIGHT: u32 = (WINDOW_HEIGHT - GRID_CELL_SIZE) as u32;
+/// Most generations run between frames, so the window stays responsive
+const MAX_GENERATIONS_PER_FRAME: usize = 4096;
+/// How often the generations per second shown in the title are measured
+const RATE_INTERVAL: Duration = Duration::from_millis(500);
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-// N.B. Trait hygiene means this (sadly) can't be a From impl
-fn point_to_rect(point: &map::MapPoint) -> Rect {
-    Rect::new(
-        point.x.into_inner() as i32,
-        point.y.into_inner() as i32,
-        GRID_CELL_SIZE as u32,
-        GRID_CELL_SIZE as u32,
-    )
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "voyager",
+    about = "A genetic approach to the travelling salesman."
+)]
+struct Opt {
+    /// Don't return to the first stop at the end of the tour
+    #[structopt(long, conflicts_with = "depot")]
+    open: bool,
+    /// Index of the stop every tour must start from
+    #[structopt(long)]
+    depot: Option<usize>,
+    /// Index of the stop every tour must end at, instead of returning to the depot
+    #[structopt(long, requires = "depot")]
+    end_depot: Option<usize>,
+    /// Capacity of each vehicle, which turns the problem into a CVRP where every stop but the
+    /// depot has a random demand
+    #[structopt(long, conflicts_with_all = &["open", "end_depot"], parse(try_from_str = parse_capacity))]
+    capacity: Option<u32>,
+    /// Width of the time window each stop gets, which turns the problem into a TSPTW (or VRPTW
+    /// alongside a capacity)
+    #[structopt(long)]
+    time_windows: Option<f64>,
+    /// How long serving each stop takes, when there are time windows
+    #[structopt(long, default_value = "0")]
+    service_time: f64,
+    /// How many units of distance a unit of lateness costs, when there are time windows
+    #[structopt(long, default_value = "10")]
+    lateness_penalty: f64,
+    /// Move late stops earlier in the tour, rather than just penalizing them
+    #[structopt(long, requires = "time_windows")]
+    repair: bool,
+    /// Maximum length of the tour, which turns the problem into an orienteering one where every
+    /// stop has a random reward and the GA picks which ones to visit
+    #[structopt(long, conflicts_with_all = &["capacity", "time_windows"])]
+    budget: Option<f64>,
+    /// File with polygonal obstacles to route around, one per line as whitespace separated x,y
+    /// vertices
+    #[structopt(long)]
+    obstacles: Option<PathBuf>,
+    /// How the stops are laid out: uniform, clusters, circle, grid or rings
+    #[structopt(long, default_value = "uniform")]
+    generator: Generator,
+    /// Grayscale image to stipple the stops from, this takes precedence over the generator
+    #[structopt(long)]
+    image: Option<PathBuf>,
+    /// CSV, JSON, text or GeoJSON file of stops to use instead of generated ones, like those
+    /// exported by another run. The first solution visits them in the order the file lists them.
+    #[structopt(long, conflicts_with = "image")]
+    load: Option<PathBuf>,
+    /// Property of the GeoJSON features that identifies their stops in exports, instead of the
+    /// features' own ids
+    #[structopt(long, requires = "load")]
+    stop_id: Option<String>,
+    /// Take the first two coordinates of the loaded stops for longitude and latitude in degrees,
+    /// and measure distances along the Earth's surface in meters. Stops loaded from GeoJSON
+    /// always are.
+    #[structopt(long, requires = "load", conflicts_with = "obstacles")]
+    geographic: bool,
+    /// Seed for the map generator, the same seed always gives the same sequence of maps
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// How many generations run between frames to begin with, up and down change it at runtime
+    #[structopt(long, default_value = "1")]
+    generations_per_frame: usize,
+    /// Most frames drawn per second, 0 draws them as fast as possible
+    #[structopt(long, default_value = "60")]
+    max_fps: u32,
+    /// Stop after this many generations
+    #[structopt(long)]
+    generations: Option<u64>,
+    /// Stop after this many solutions have been scored, so solvers can be compared on an equal
+    /// budget. The distances ants, 2-opt and local search measure to build or improve tours count
+    /// too, each tour's worth of them as one evaluation.
+    #[structopt(long)]
+    evaluations: Option<u64>,
+    /// Run without opening a window, which needs a number of generations or evaluations to stop
+    /// after
+    #[structopt(long)]
+    headless: bool,
+    /// Polish the best few solutions with Or-opt and Lin-Kernighan moves every this many
+    /// generations, which makes the GA a memetic algorithm
+    #[structopt(long)]
+    optimize_every: Option<u64>,
+    /// How many of the best solutions get polished
+    #[structopt(long, default_value = "4")]
+    optimize_elite: usize,
+    /// Print how often each mutation operator is applied and what comes of it every generation,
+    /// and over the whole run when it ends
+    #[structopt(long)]
+    stats: bool,
+    /// What searches for tours: ga (the genetic algorithm), sa (simulated annealing), aco (ant
+    /// colony optimization) or tabu (tabu search)
+    #[structopt(long, default_value = "ga")]
+    solver: Solver,
+    /// Run the GA in steady state: each generation breeds this many offspring, which take the
+    /// place of as many solutions, rather than replacing the whole population
+    #[structopt(long)]
+    steady_state: Option<usize>,
+    /// Which solutions the offspring replace in steady state: worst, oldest, random or tournament
+    /// (the worst of a few drawn at random). The best solution is never replaced, and replacing
+    /// the worst ones can leave the population too alike to improve on it.
+    #[structopt(long, default_value = "tournament")]
+    replacement: Replacement,
+    /// Where to save the best tour when the run ends, as an SVG or a PNG, or its stops in order as
+    /// a CSV, JSON, text or GeoJSON file
+    #[structopt(long)]
+    export: Option<PathBuf>,
+    /// Where to save the lineage of the best solution when the run ends, as a DOT graph or JSON.
+    /// Every solution then records its parents, the generation it was born in and what the
+    /// operators did to it, which takes some memory.
+    #[structopt(long)]
+    genealogy: Option<PathBuf>,
+    /// Where to record the run as an animated GIF
+    #[structopt(long)]
+    record: Option<PathBuf>,
+    /// How many generations apart recorded frames are
+    #[structopt(long, default_value = "10")]
+    record_every: u64,
+    /// Which tours to show to begin with: best, overlay or grid. Exports and recordings show the
+    /// same ones as the window.
+    #[structopt(long, default_value = "best")]
+    view: View,
+    /// File of changes to the map over time, one per line as `<generation> add x,y [dx,dy]`,
+    /// `<generation> remove x,y` or `<generation> drift x,y dx,dy`. Stops are removed or set
+    /// drifting by whichever is closest to the point.
+    #[structopt(long)]
+    changes: Option<PathBuf>,
+    /// Set every stop but the depots drifting in a random direction, at up to this many units of
+    /// distance a generation
+    #[structopt(long)]
+    drift: Option<f64>,
+    /// How many of the best solutions to remember, they're put back in the population whenever
+    /// stops appear or disappear
+    #[structopt(long, default_value = "0")]
+    memory: usize,
+    /// Share of the population replaced by random solutions each generation, while the map
+    /// changes, from 0 up to but not including 1. The best solution is never replaced.
+    #[structopt(long, default_value = "0", parse(try_from_str = parse_rate))]
+    immigrants: f64,
+    /// How many of the initial solutions are nearest neighbor tours rather than random ones
+    #[structopt(long, default_value = "0")]
+    greedy: usize,
+    /// Objectives to trade off against each other instead of optimizing the score, comma
+    /// separated: length, longest-leg and turning. The GA then keeps a front of solutions where
+    /// none is better than another in all of them.
+    #[structopt(long, use_delimiter = true, min_values = 2, conflicts_with = "budget")]
+    objectives: Vec<Objective>,
+    /// Where to save the Pareto front when the run ends, as a CSV
+    #[structopt(long, requires = "objectives")]
+    pareto: Option<PathBuf>,
+    /// How many coordinates each stop has, from 2 to 6. Maps with more than two are shown turned
+    /// around their center, dragging with the middle button and shift held turns them.
+    #[structopt(long, default_value = "2")]
+    dimensions: usize,
+    /// What coordinates are stored as: i32, f32 or f64. Integer coordinates have distances
+    /// rounded to the nearest integer like TSPLIB's, f32 ones take half the memory of f64 ones.
+    #[structopt(long, default_value = "f64")]
+    precision: Precision,
+}
+
+impl Opt {
+    /// The depot's index, vehicle routing problems always have one
+    fn depot(&self) -> Option<usize> {
+        self.depot.or_else(|| self.capacity.map(|_| 0))
+    }
+
+    fn tour_mode(&self) -> TourMode {
+        match self.depot() {
+            Some(depot) => TourMode::Depot {
+                end: self.end_depot.filter(|&end| end != depot).is_some(),
+            },
+            None if self.open => TourMode::Open,
+            None => TourMode::Closed,
+        }
+    }
+
+    /// Whether distances are measured along the Earth's surface
+    fn geographic(&self) -> bool {
+        self.geographic || self.load.as_deref().is_some_and(tour::is_geographic)
+    }
+
+    /// Loads the travel map along with the ids of its stops, or generates a random one clear of
+    /// obstacles, with the depots (if any) where the tour mode expects them
+    fn travel_map<P: Stop>(
+        &self,
+        generator: &Generator,
+        rng: &mut StdRng,
+        obstacles: &[Polygon],
+    ) -> Result<(Map<P>, StopIds<P>), Box<dyn std::error::Error>> {
+        let blocked = |pt: &P| obstacles.iter().any(|polygon| polygon.contains(pt));
+        let (mut travel_map, ids) = match &self.load {
+            Some(path) => {
+                let (travel_map, ids): (Map<P>, _) = tour::load(path, self.stop_id.as_deref())?;
+                if let Some(stop) = travel_map.iter().find(|&pt| blocked(pt)) {
+                    return Err(format!("stop {} is inside an obstacle", stop).into());
+                }
+                (travel_map, ids)
+            }
+            None => {
+                let travel_map =
+                    generator.generate(MAP_WIDTH, MAP_HEIGHT, TSP_STOPS, rng, |pt| !blocked(pt))?;
+                (travel_map, StopIds::new())
+            }
+        };
+        let min = self.tour_mode().min_stops();
+        if travel_map.len() < min {
+            let source = match &self.load {
+                Some(path) => path.display().to_string(),
+                None => "the generated map".to_string(),
+            };
+            let message = format!(
+                "a tour needs at least {} stops, {} has {}",
+                min,
+                source,
+                travel_map.len()
+            );
+            return Err(message.into());
+        }
+        if let Some(depot) = self.depot() {
+            let len = travel_map.len();
+            if let Some(idx) = once(depot).chain(self.end_depot).find(|&idx| idx >= len) {
+                return Err(format!("there's no stop {} on a map of {} stops", idx, len).into());
+            }
+            travel_map.pin(depot, self.end_depot);
+        }
+        Ok((travel_map, ids))
+    }
+
+    /// How the map changes over time, starting from `travel_map`. Stops drift within its bounding
+    /// box.
+    fn dynamics<P: Stop>(&self, changes: &[Change<P>], travel_map: &Map<P>) -> Dynamics<P> {
+        let mut dynamics = Dynamics::new(
+            changes.to_vec(),
+            travel_map.bounds(),
+            MIN_STOPS,
+            self.memory,
+            self.immigrants,
+        );
+        if let Some(speed) = self.drift {
+            let free = self.tour_mode().free_range(travel_map.len());
+            dynamics.drift(&travel_map[free], speed);
+        }
+        dynamics
+    }
+
+    fn problem<P: Stop>(
+        &self,
+        travel_map: &Map<P>,
+        ids: StopIds<P>,
+        obstacles: &[Polygon],
+    ) -> Result<Arc<Problem<P>>, Box<dyn std::error::Error>> {
+        let fleet = self
+            .capacity
+            .map(|capacity| Fleet::random(travel_map, capacity, MAX_DEMAND.min(capacity)))
+            .transpose()?;
+        let mode = self.tour_mode();
+        let metric = metric(travel_map, obstacles, self.geographic());
+        if let Metric::Terrain(terrain) = &metric {
+            if let Some((a, b)) = terrain.unreachable() {
+                let message = format!("the obstacles leave no way from stop {} to stop {}", a, b);
+                return Err(message.into());
+            }
+        }
+        let schedule = self.time_windows.map(|width| {
+            let weight = self.lateness_penalty;
+            let strategy = if self.repair {
+                Strategy::Repair { weight }
+            } else {
+                Strategy::Penalty { weight }
+            };
+            let stops = mode.free_range(travel_map.len());
+            Schedule::random(
+                travel_map,
+                stops,
+                width,
+                self.service_time,
+                strategy,
+                &metric,
+            )
+        });
+        let orienteering = self.budget.map(|budget| {
+            let stops = mode.free_range(travel_map.len());
+            Orienteering::random(travel_map, stops, budget, MAX_REWARD)
+        });
+        Ok(Arc::new(Problem {
+            mode,
+            neighbors: Arc::new(Neighbors::new(travel_map, &metric)),
+            metric,
+            fleet,
+            schedule,
+            orienteering,
+            objectives: self.objectives.clone(),
+            ids,
+            evaluations: Arc::default(),
+            genealogy: self.genealogy.as_ref().map(|_| Arc::default()),
+        }))
+    }
+
+    /// The solver's state to begin with
+    fn search<P: Stop>(&self) -> Search<P> {
+        match self.steady_state {
+            Some(count) => Search::SteadyState(SteadyState::new(count, self.replacement)),
+            None => Search::new(self.solver),
+        }
+    }
+
+    /// How many solutions the solver keeps at once. Simulated annealing and tabu search keep the
+    /// best one so far and the one they're at.
+    fn population_size(&self) -> usize {
+        match self.solver {
+            Solver::Genetic => GENERATION_SIZE,
+            Solver::Colony => colony::ANTS,
+            Solver::Annealing | Solver::Tabu => 2,
+        }
+    }
+
+    /// The first generation, a few nearest neighbor tours and random ones for the rest. A loaded
+    /// map's own order comes first, so a tour saved by another run picks up where it left off.
+    fn population<P: Stop>(
+        &self,
+        travel_map: &Map<P>,
+        problem: &Arc<Problem<P>>,
+    ) -> Vec<Chromosome<P>> {
+        let size = self.population_size();
+        let loaded = usize::from(self.load.is_some());
+        let greedy = (loaded + self.greedy).min(size);
+        let mut population: Vec<_> = (0..size)
+            .into_par_iter()
+            .map(|idx| {
+                if idx < loaded {
+                    Chromosome::new(travel_map.clone(), problem.clone())
+                } else if idx < greedy {
+                    Chromosome::greedy(travel_map, problem)
+                } else {
+                    Chromosome::random(travel_map, problem)
+                }
+            })
+            .collect();
+        // Searches that go from one solution to the next start from the best one
+        if matches!(self.solver, Solver::Annealing | Solver::Tabu) {
+            rank(&mut population);
+            population[1] = population[0].clone();
+        }
+        population
+    }
+
+    /// Polishes the best few of `population` with local search, if it's due at `generation`
+    fn optimize<P: Stop>(&self, generation: u64, population: &mut [Chromosome<P>]) {
+        if self
+            .optimize_every
+            .is_some_and(|every| generation.is_multiple_of(every))
+        {
+            rank(population);
+            let elite = self.optimize_elite.min(population.len());
+            population[..elite]
+                .par_iter_mut()
+                .for_each(Chromosome::optimize);
+        }
+    }
+
+    /// Adds what the mutation operators did this generation to the run's `total`, printing it
+    /// too if asked to
+    fn report<P: Stop>(
+        &self,
+        search: &mut Search<P>,
+        total: &mut Stats,
+        generation: u64,
+        pb: &ProgressBar,
+    ) {
+        if let Some(stats) = search.take_stats() {
+            if self.stats {
+                let line = format!("generation {}: {}", generation, stats);
+                // The bar only prints lines while it's shown
+                if pb.is_hidden() {
+                    eprintln!("{}", line);
+                } else {
+                    pb.println(line);
+                }
+            }
+            total.merge(&stats);
+        }
+    }
+
+    /// Whether the run has used up the generations or evaluations it was given
+    fn done(&self, hud: &Hud) -> bool {
+        self.generations.is_some_and(|max| hud.generation >= max)
+            || self.evaluations.is_some_and(|max| hud.evaluations >= max)
+    }
+}
+
+/// Parses a vehicle capacity, which has to fit at least one unit of demand
+fn parse_capacity(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(0) => Err("vehicles need a capacity of at least 1".to_string()),
+        Ok(capacity) => Ok(capacity),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parses a share of something, which is at least 0 and less than 1
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s
+        .parse()
+        .map_err(|err: std::num::ParseFloatError| err.to_string())?;
+    if (0.0..1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("{} isn't at least 0 and less than 1", rate))
+    }
+}
+
+fn metric<P: Stop>(travel_map: &Map<P>, obstacles: &[Polygon], geographic: bool) -> Metric<P> {
+    if geographic {
+        Metric::Haversine
+    } else if obstacles.is_empty() {
+        Metric::Euclidean
+    } else {
+        Metric::Terrain(Arc::new(Terrain::new(obstacles, travel_map)))
+    }
+}
+
+/// Whether a stop at `point` could be visited, rather than being walled off by obstacles
+fn reachable<P: Stop>(problem: &Problem<P>, point: P) -> bool {
+    match &problem.metric {
+        Metric::Terrain(terrain) => terrain.reaches(point),
+        _ => true,
+    }
+}
+
+/// Applies edits to the travel map, and adapts the problem and the solutions to them. With no
+/// edits this just rescores the solutions, after the obstacles change.
+fn edit<P: Stop>(
+    edits: &[Edit<P>],
+    travel_map: &mut Map<P>,
+    obstacles: &[Polygon],
+    problem: &mut Arc<Problem<P>>,
+    parents: &mut Vec<Chromosome<P>>,
+    dynamics: &mut Dynamics<P>,
+) {
+    let mut updated = (**problem).clone();
+    for &edit in edits {
+        travel_map.edit(edit);
+        updated.edit(edit);
+    }
+    let moves = !edits.is_empty() && edits.iter().all(|e| matches!(e, Edit::Move { .. }));
+    match &mut updated.metric {
+        // Only the paths of the stops that moved change, as long as the obstacles don't
+        Metric::Terrain(terrain) if moves => {
+            let terrain = Arc::make_mut(terrain);
+            for &edit in edits {
+                if let Edit::Move { from, to } = edit {
+                    terrain.moved(from, to);
+                }
+            }
+        }
+        _ => {
+            let geographic = matches!(updated.metric, Metric::Haversine);
+            updated.metric = metric(travel_map, obstacles, geographic);
+        }
+    }
+    updated.neighbors = Arc::new(Neighbors::new(travel_map, &updated.metric));
+    *problem = Arc::new(updated);
+    *parents = parents
+        .par_drain(..)
+        .map(|c| c.edited(edits, problem.clone()))
+        .collect();
+    dynamics.edit(edits, problem);
+}
+
+/// Brings the travel map up to the current generation, if it changes over time, and helps the
+/// population follow it
+fn change<P: Stop>(
+    generation: u64,
+    travel_map: &mut Map<P>,
+    obstacles: &[Polygon],
+    problem: &mut Arc<Problem<P>>,
+    parents: &mut Vec<Chromosome<P>>,
+    dynamics: &mut Dynamics<P>,
+) {
+    if dynamics.is_static() {
+        return;
+    }
+    let free = &travel_map[problem.mode.free_range(travel_map.len())];
+    // Stops can't drift into obstacles, nor jump over them somewhere cut off from the rest
+    let blocked =
+        |pt: &P| obstacles.iter().any(|polygon| polygon.contains(pt)) || !reachable(problem, *pt);
+    let (edits, changed) = dynamics.step(generation, travel_map, free, blocked);
+    if !edits.is_empty() {
+        edit(&edits, travel_map, obstacles, problem, parents, dynamics);
+    }
+    rank(parents);
+    dynamics.adapt(generation, changed, parents, travel_map, problem);
+}
+
+/// Breeds the next generation from `parents` into `children`, and then swaps them. Solvers other
+/// than the GA take their own step from `parents` instead.
+fn generation<P: Stop>(
+    search: &mut Search<P>,
+    parents: &mut Vec<Chromosome<P>>,
+    children: &mut Vec<Chromosome<P>>,
+) -> Result<(), WeightedError> {
+    let scratch = match search {
+        Search::Genetic(scratch) => scratch,
+        Search::SteadyState(steady) => return steady.step(parents),
+        Search::Annealing(annealing) => {
+            annealing.step(parents);
+            return Ok(());
+        }
+        Search::Colony(colony) => {
+            colony.step(parents);
+            return Ok(());
+        }
+        Search::Tabu(tabu) => {
+            tabu.step(parents);
+            return Ok(());
+        }
+    };
+    if !parents[0].problem.objectives.is_empty() {
+        pareto::generation(parents, children, scratch);
+        return Ok(());
+    }
+
+    // Sort by the smallest score
+    rank(parents);
+    // Last generation's solutions are overwritten, there are only new ones the first time
+    let remainder = GENERATION_SIZE - PARENTS_SUVIVE;
+    children.truncate(PARENTS_SUVIVE + remainder / 2 * 2);
+    children.resize_with(PARENTS_SUVIVE + remainder / 2 * 2, || parents[0].clone());
+    let (survivors, offspring) = children.split_at_mut(PARENTS_SUVIVE);
+
+    // Copy the N best to the children set unchanged
+    survivors.clone_from_slice(&parents[0..PARENTS_SUVIVE]);
+
+    // Crossover the remaining parents into children
+    let score: Vec<f64> = parents.iter().map(|c| c.score).collect();
+    let dist = WeightedIndex::new(&score)?;
+    chromosome::breed(parents, offspring, scratch, |rng| {
+        let a = dist.sample(rng);
+        let mut b = dist.sample(rng);
+        while a == b {
+            b = dist.sample(rng);
+        }
+        (a, b)
+    });
+
+    // Continue with the children, the parents are next generation's children
+    std::mem::swap(parents, children);
+    Ok(())
+}
+
+/// Sorts the population best first, the next generation only keeps the best ones in order
+fn rank<P: Stop>(population: &mut [Chromosome<P>]) {
+    population.par_sort_unstable_by(|a, b| a.cmp(b).reverse());
+}
+
+/// Runs a generation and keeps the HUD's figures up to date, recording a frame if one is due
+fn advance<P: Stop>(
+    search: &mut Search<P>,
+    parents: &mut Vec<Chromosome<P>>,
+    children: &mut Vec<Chromosome<P>>,
+    hud: &mut Hud,
+    recorder: Option<(&mut Recorder, u64)>,
+    view: View,
+    obstacles: &[Polygon],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(genealogy) = &parents[0].problem.genealogy {
+        genealogy.set_generation(hud.generation + 1);
+    }
+    generation(search, parents, children)?;
+    hud.generation += 1;
+    hud.evaluations = parents[0].problem.evaluations.load(Ordering::Relaxed);
+    hud.best = parents[0].length();
+    hud.history.push(hud.best);
+    if let Some((recorder, every)) = recorder {
+        if hud.generation.is_multiple_of(every) {
+            hud.mean = parents.par_iter().map(|c| c.length()).sum::<f64>() / parents.len() as f64;
+            rank(parents);
+            recorder.record(&Scene {
+                population: parents,
+                view,
+                hud: Some(hud),
+                heatmap: false,
+                obstacles,
+                placing: None,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Saves the tours in view if asked to, and prints the best one's routes since they're otherwise
+/// only visible in the window
+fn finish<P: Stop>(
+    opt: &Opt,
+    population: &mut [Chromosome<P>],
+    view: View,
+    obstacles: &[Polygon],
+    stats: &Stats,
+) -> Result<(), Box<dyn std::error::Error>> {
+    rank(population);
+    if opt.stats {
+        print!("{}", stats.summary());
+    }
+    if let Some(path) = &opt.pareto {
+        pareto::save(path, &pareto::front(population))?;
+    }
+    let best = &population[0];
+    if let (Some(path), Some(birth)) = (&opt.genealogy, &best.birth) {
+        genealogy::save(path, birth)?;
+    }
+    if let Some(path) = &opt.export {
+        export::save(
+            path,
+            &Scene {
+                population,
+                view,
+                hud: None,
+                heatmap: false,
+                obstacles,
+                placing: None,
+            },
+        )?;
+    }
+
+    if let (Some(routes), Some(fleet)) = (best.routes(), &best.problem.fleet) {
+        let depot = best.solution[0];
+        for (idx, route) in routes.iter().enumerate() {
+            let stops: Map<P> = once(depot)
+                .chain(route.iter().copied())
+                .chain(once(depot))
+                .collect::<MapInner<P>>()
+                .into();
+            println!(
+                "vehicle {}: load {}/{} | {}",
+                idx + 1,
+                fleet.load(route),
+                fleet.capacity,
+                stops
+            );
+        }
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opt = Opt::from_args();
+    if opt.headless && opt.generations.is_none() && opt.evaluations.is_none() {
+        return Err("--headless needs --generations or --evaluations to know when to stop".into());
+    }
+    if opt.solver != Solver::Genetic && !opt.objectives.is_empty() {
+        return Err("only the ga solver can trade off several objectives".into());
+    }
+    if let Some(count) = opt.steady_state {
+        if opt.solver != Solver::Genetic {
+            return Err("only the ga solver can run in steady state".into());
+        }
+        if !opt.objectives.is_empty() {
+            return Err("a steady-state GA can't trade off several objectives".into());
+        }
+        if count == 0 || count % 2 != 0 || count >= GENERATION_SIZE {
+            return Err(format!(
+                "--steady-state needs an even number of offspring below {}, as they're bred in pairs",
+                GENERATION_SIZE
+            )
+            .into());
+        }
+    }
+    if opt.solver != Solver::Genetic && opt.stats {
+        return Err("only the ga solver has mutation operators to report on".into());
+    }
+    if opt.solver != Solver::Genetic && opt.genealogy.is_some() {
+        return Err("only the ga solver keeps a genealogy".into());
+    }
+    if opt.dimensions != 2 && opt.obstacles.is_some() {
+        return Err("obstacles can only be used on maps with two dimensions".into());
+    }
+    if opt.dimensions != 2 && opt.geographic() {
+        return Err("geographic maps can only have two dimensions".into());
+    }
+    if opt.geographic() && opt.obstacles.is_some() {
+        return Err("obstacles can't be used on geographic maps".into());
+    }
+    if opt.geographic() && opt.precision == Precision::I32 {
+        return Err(
+            "geographic maps can't have integer coordinates, as degrees are too coarse".into(),
+        );
+    }
+    match opt.precision {
+        Precision::I32 => dimensions::<i32>(opt),
+        Precision::F32 => dimensions::<OrderedFloat<f32>>(opt),
+        Precision::F64 => dimensions::<MapUnit>(opt),
+    }
+}
+
+/// Runs the GA on maps with as many dimensions as asked for, whose coordinates are `T`s
+fn dimensions<T: Coordinate>(opt: Opt) -> Result<(), Box<dyn std::error::Error>> {
+    match opt.dimensions {
+        2 => run::<Point<2, T>>(opt),
+        3 => run::<Point<3, T>>(opt),
+        4 => run::<Point<4, T>>(opt),
+        5 => run::<Point<5, T>>(opt),
+        6 => run::<Point<6, T>>(opt),
+        n => Err(format!("maps can have 2 to 6 dimensions, not {}", n).into()),
+    }
+}
+
+/// Runs the GA on maps whose stops are `P`s
+fn run<P: Stop>(opt: Opt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut obstacles = match &opt.obstacles {
+        Some(path) => terrain::load(path)?,
+        None => Vec::new(),
+    };
+    // Vertices of the obstacle being placed with the mouse, if any
+    let mut placing: Option<Vec<MapPoint>> = None;
+    // The stop being dragged with the mouse, and where it's been dragged to so far
+    let mut dragging: Option<(P, P)> = None;
+    let generator = match &opt.image {
+        Some(path) => Generator::image(path)?,
+        None => opt.generator.clone(),
+    };
+    let mut map_rng = opt
+        .seed
+        .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+
     // First we create a random map of the appropriate size
-    let travel_map = map::random_map(
-        (WINDOW_WIDTH - GRID_CELL_SIZE) as u32,
-        (WINDOW_HEIGHT - GRID_CELL_SIZE) as u32,
-        TSP_STOPS,
-    );
-    // We fill the parent generation with random permutations of the initial travel_map
-    let mut parents: Vec<Chromosome> = std::iter::repeat_with(|| Chromosome::random(&travel_map))
-        .take(GENERATION_SIZE)
-        .collect();
-    // Children start empty, they're used dduring crossover
-    let mut children: Vec<Chromosome> = Vec::with_capacity(GENERATION_SIZE);
+    let (mut travel_map, ids): (Map<P>, _) =
+        opt.travel_map(&generator, &mut map_rng, &obstacles)?;
+    let mut problem = opt.problem(&travel_map, ids, &obstacles)?;
+    let changes = match &opt.changes {
+        Some(path) => dynamic::load(path)?,
+        None => Vec::new(),
+    };
+    let mut dynamics = opt.dynamics(&changes, &travel_map);
+    // We fill the parent generation with permutations of the initial travel_map
+    let mut parents = opt.population(&travel_map, &problem);
+    // Children start empty, they're filled during crossover and reused after that
+    let mut children: Vec<Chromosome<P>> = Vec::with_capacity(GENERATION_SIZE);
+    let mut search = opt.search();
+    let mut hud = Hud::default();
+    // What the mutation operators did over the whole run
+    let mut stats = Stats::default();
+    let mut recorder = opt.record.as_deref().map(Recorder::new).transpose()?;
+
+    if opt.headless {
+        // The bar follows evaluations if there's a budget of them, and generations otherwise
+        let pb = ProgressBar::new(opt.evaluations.or(opt.generations).unwrap_or_default())
+            .with_style(
+                ProgressStyle::default_bar()
+                    .template("{elapsed_precise} | {per_sec} | {wide_bar} {pos}/{len}"),
+            );
+        while !opt.done(&hud) {
+            change(
+                hud.generation,
+                &mut travel_map,
+                &obstacles,
+                &mut problem,
+                &mut parents,
+                &mut dynamics,
+            );
+            opt.optimize(hud.generation, &mut parents);
+            let recording = recorder.as_mut().map(|r| (r, opt.record_every));
+            advance(
+                &mut search,
+                &mut parents,
+                &mut children,
+                &mut hud,
+                recording,
+                opt.view,
+                &obstacles,
+            )?;
+            opt.report(&mut search, &mut stats, hud.generation, &pb);
+            pb.set_position(match opt.evaluations {
+                Some(max) => hud.evaluations.min(max),
+                None => hud.generation,
+            });
+        }
+        pb.finish();
+        return finish(&opt, &mut parents, opt.view, &obstacles, &stats);
+    }
 
     // SDL windowing nonsense
     sdl2::hint::set("SDL_HINT_RENDER_SCALE_QUALITY", "1");
@@ -61,8 +872,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .window("voyager", WINDOW_WIDTH as u32, WINDOW_HEIGHT as u32)
         .opengl()
         .position_centered()
+        .resizable()
         .build()?;
-    let mut canvas = window.into_canvas().accelerated().build()?;
+    let mut renderer = Renderer::new(window.into_canvas().accelerated().build()?)?;
+    renderer.rotates = P::DIMENSIONS > 2;
+    renderer.viewport.north_up = opt.geographic();
+    renderer.viewport.fit(travel_map.iter());
+
+    // Playback controls
+    let mut paused = false;
+    let mut step = false;
+    let mut generations_per_frame = opt
+        .generations_per_frame
+        .clamp(1, MAX_GENERATIONS_PER_FRAME);
+    let frame_time = match opt.max_fps {
+        0 => Duration::from_secs(0),
+        fps => Duration::from_secs(1) / fps,
+    };
+    // Whether to shade the edges the population uses beneath the best tour
+    let mut heatmap = false;
+    let mut view = opt.view;
+    // Which member of the Pareto front is shown in front of the others
+    let mut selected: usize = 0;
+    // When the generations per second were last measured, and the generation at the time
+    let mut rate_start = (Instant::now(), hud.generation);
 
     // Our progress spinner
     let pb = ProgressBar::new_spinner().with_style(
@@ -71,7 +904,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // The main event loop
     'running: loop {
+        let frame_start = Instant::now();
         for event in event_pump.poll_iter() {
+            if renderer.handle(&event) {
+                continue;
+            }
             match event {
                 // Esc and Q exit
                 Event::Quit { .. }
@@ -83,86 +920,289 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     keycode: Some(Keycode::Q),
                     ..
                 } => break 'running,
-                // R generates a new random travel_map and resets the program
+                // R generates a new travel_map and resets the program
                 Event::KeyDown {
                     keycode: Some(Keycode::R),
                     ..
                 } => {
-                    let travel_map = map::random_map(
-                        (WINDOW_WIDTH - GRID_CELL_SIZE) as u32,
-                        (WINDOW_HEIGHT - GRID_CELL_SIZE) as u32,
-                        TSP_STOPS,
-                    );
-                    parents = std::iter::repeat_with(|| Chromosome::random(&travel_map))
-                        .take(GENERATION_SIZE)
-                        .collect();
+                    let (map, ids) = opt.travel_map(&generator, &mut map_rng, &obstacles)?;
+                    travel_map = map;
+                    problem = opt.problem(&travel_map, ids, &obstacles)?;
+                    dynamics = opt.dynamics(&changes, &travel_map);
+                    parents = opt.population(&travel_map, &problem);
+                    search = opt.search();
                     children.clear();
+                    dragging = None;
+                    renderer.viewport.fit(travel_map.iter());
+                    hud = Hud::default();
+                    stats = Stats::default();
+                    rate_start = (Instant::now(), hud.generation);
                     pb.reset();
                 }
+                // Space pauses and resumes the GA
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => paused = !paused,
+                // N runs a single generation while paused
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => step = true,
+                // H toggles the edge heatmap
+                Event::KeyDown {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => heatmap = !heatmap,
+                // V cycles through the views, and the brackets show fewer or more tours in them
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } => view = view.next(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::LeftBracket),
+                    ..
+                } => view = view.resize(-1),
+                Event::KeyDown {
+                    keycode: Some(Keycode::RightBracket),
+                    ..
+                } => view = view.resize(1),
+                // Comma and period step through the members of the Pareto front
+                Event::KeyDown {
+                    keycode: Some(Keycode::Comma),
+                    ..
+                } => selected = selected.saturating_sub(1),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    ..
+                } => selected += 1,
+                // Up and down double and halve how many generations run each frame
+                Event::KeyDown {
+                    keycode: Some(Keycode::Up),
+                    ..
+                } => {
+                    generations_per_frame =
+                        (generations_per_frame * 2).min(MAX_GENERATIONS_PER_FRAME)
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Down),
+                    ..
+                } => generations_per_frame = (generations_per_frame / 2).max(1),
+                // F fits the whole map back into the window
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => renderer.viewport.fit(travel_map.iter()),
+                // P starts placing an obstacle, and pressing it again finishes it
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => match placing.take() {
+                    None if P::DIMENSIONS > 2 => {
+                        pb.println("Obstacles can only be placed on maps with two dimensions")
+                    }
+                    None => placing = Some(Vec::new()),
+                    Some(vertices) if vertices.len() < 3 => {}
+                    Some(vertices) => {
+                        let polygon = Polygon(vertices);
+                        if travel_map.iter().any(|pt| polygon.contains(pt)) {
+                            pb.println("Obstacles can't cover stops, discarding it");
+                            continue;
+                        }
+                        obstacles.push(polygon);
+                        let terrain = Terrain::new(&obstacles, &travel_map);
+                        if terrain.unreachable().is_some() {
+                            pb.println("Obstacles can't cut stops off, discarding it");
+                            obstacles.pop();
+                            continue;
+                        }
+                        // Only the path costs changed, so we keep the population and rescore it
+                        edit(
+                            &[],
+                            &mut travel_map,
+                            &obstacles,
+                            &mut problem,
+                            &mut parents,
+                            &mut dynamics,
+                        );
+                    }
+                },
+                // Left clicks add vertices to the obstacle being placed, otherwise they pick up the
+                // stop under the mouse to drag it, or add a new stop if there isn't one. Coarse
+                // coordinates can round a click onto a stop out of reach, which isn't added twice.
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let point: P = renderer.viewport.to_world(x, y);
+                    if let Some(vertices) = &mut placing {
+                        vertices.push(renderer.viewport.to_world(x, y));
+                    } else if matches!(view, View::Grid(_)) {
+                        // Stops can only be edited where the window shows a single map
+                    } else if let Some(stop) = renderer.viewport.pick(travel_map.iter(), x, y) {
+                        dragging = Some((stop, stop));
+                    } else if !obstacles.iter().any(|polygon| polygon.contains(&point))
+                        && !travel_map.contains(&point)
+                        && reachable(&problem, point)
+                    {
+                        edit(
+                            &[Edit::Add(point)],
+                            &mut travel_map,
+                            &obstacles,
+                            &mut problem,
+                            &mut parents,
+                            &mut dynamics,
+                        );
+                    }
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some((_, target)) = &mut dragging {
+                        *target = renderer.viewport.to_world(x, y);
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => dragging = None,
+                // Right clicks remove the stop under the mouse, unless it's a depot
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Right,
+                    x,
+                    y,
+                    ..
+                } if placing.is_none()
+                    && !matches!(view, View::Grid(_))
+                    && travel_map.len() > MIN_STOPS =>
+                {
+                    let free = &travel_map[problem.mode.free_range(travel_map.len())];
+                    if let Some(stop) = renderer.viewport.pick(free, x, y) {
+                        edit(
+                            &[Edit::Remove(stop)],
+                            &mut travel_map,
+                            &obstacles,
+                            &mut problem,
+                            &mut parents,
+                            &mut dynamics,
+                        );
+                    }
+                }
                 _ => {}
             }
         }
-        pb.set_message(&format!("score: {}", parents[0].score));
-
-        // Plot the points
-        canvas.set_draw_color(COLOR_BACKGROUND);
-        canvas.clear();
-        canvas.set_draw_color(COLOR_ENTITY);
-        parents[0]
-            .solution
-            .iter()
-            .map(point_to_rect)
-            .try_for_each(|rect| canvas.fill_rect(rect))?;
-
-        // Plot the paths
-        canvas.set_draw_color(COLOR_PATH);
-        parents[0]
-            .solution
-            .iter()
-            .tuple_windows::<(_, _)>()
-            .map(|p| (point_to_rect(&p.0), point_to_rect(&p.1)))
-            .map(|p| (p.0.center(), p.1.center()))
-            .try_for_each(|(a, b)| canvas.draw_line(a, b))?;
-        // Plot the closing path
-        let start = point_to_rect(&parents[0].solution[0]).center();
-        let end = point_to_rect(&parents[0].solution.last().unwrap()).center();
-        canvas.draw_line(start, end)?;
-        canvas.present();
-
-        // Sort by the smallest score
-        parents.par_sort_unstable_by(|a, b| a.cmp(b).reverse());
-        // Copy the N best to the children set unchanged
-        children.extend_from_slice(&parents[0..PARENTS_SUVIVE]);
-
-        // Crossover the remaining parents into children
-        let remainder = GENERATION_SIZE - PARENTS_SUVIVE;
-        let score: Vec<f64> = parents.iter().map(|c| c.score).collect();
-        let dist = WeightedIndex::new(&score)?;
-
-        children.par_extend(
-            (0..(remainder / 2))
-                .into_par_iter()
-                .map(|_| {
-                    let mut local_rng = thread_rng();
-                    let a = dist.sample(&mut local_rng);
-                    let mut b = dist.sample(&mut local_rng);
-                    while a == b {
-                        b = dist.sample(&mut local_rng);
-                    }
+        // Drags are applied at most once a frame, since the metric is updated for each of them
+        if let Some((from, to)) = dragging.filter(|(from, to)| from != to) {
+            let blocked = obstacles.iter().any(|polygon| polygon.contains(&to));
+            if !blocked && !travel_map.contains(&to) && reachable(&problem, to) {
+                edit(
+                    &[Edit::Move { from, to }],
+                    &mut travel_map,
+                    &obstacles,
+                    &mut problem,
+                    &mut parents,
+                    &mut dynamics,
+                );
+                dragging = Some((to, to));
+            }
+        }
 
-                    let (mut son, mut daughter) = parents[a].clone().crossover(parents[b].clone());
-                    son.mutate();
-                    daughter.mutate();
-                    (son, daughter)
-                })
-                .flat_map(|(a, b)| rayon::iter::once(a).chain(rayon::iter::once(b))),
-        );
+        let mut message = format!("score: {}", parents[0].score);
+        if let Some(routes) = parents[0].routes() {
+            message += &format!(" | vehicles: {}", routes.len());
+        }
+        if let Some(violations) = parents[0].violations() {
+            message += &format!(" | late: {}", violations);
+        }
+        if let (Some(visits), Some(orienteering)) = (parents[0].visits(), &problem.orienteering) {
+            message += &format!(
+                " | reward: {}/{} | length: {:.0}/{}",
+                visits.reward,
+                orienteering.total(),
+                visits.length,
+                orienteering.budget
+            );
+        }
+        // With several objectives the window shows the front instead, the selected member first
+        let front = if problem.objectives.is_empty() {
+            None
+        } else {
+            let mut front = pareto::front(&parents);
+            selected = selected.min(front.len() - 1);
+            front[..=selected].rotate_right(1);
+            for (objective, value) in problem.objectives.iter().zip(&front[0].objectives) {
+                message += &format!(" | {}: {:.2}", objective.name(), value);
+            }
+            message += &format!(" | front: {}/{}", selected + 1, front.len());
+            Some(front)
+        };
+        pb.set_message(&message);
 
-        // Cleanup and continue
-        std::mem::swap(&mut parents, &mut children);
-        children.clear();
-        pb.inc(1);
+        hud.paused = paused;
+        hud.best = parents[0].length();
+        hud.mean = parents.par_iter().map(|c| c.length()).sum::<f64>() / parents.len() as f64;
+        rank(&mut parents);
+        renderer.draw(&Scene {
+            population: front.as_deref().unwrap_or(&parents),
+            view,
+            hud: Some(&hud),
+            heatmap,
+            obstacles: &obstacles,
+            placing: placing.as_deref(),
+        })?;
+
+        let generations = match (paused, step) {
+            (false, _) => generations_per_frame,
+            (true, true) => 1,
+            (true, false) => 0,
+        };
+        step = false;
+        for _ in 0..generations {
+            change(
+                hud.generation,
+                &mut travel_map,
+                &obstacles,
+                &mut problem,
+                &mut parents,
+                &mut dynamics,
+            );
+            opt.optimize(hud.generation, &mut parents);
+            let recording = recorder.as_mut().map(|r| (r, opt.record_every));
+            advance(
+                &mut search,
+                &mut parents,
+                &mut children,
+                &mut hud,
+                recording,
+                view,
+                &obstacles,
+            )?;
+            opt.report(&mut search, &mut stats, hud.generation, &pb);
+            pb.inc(1);
+            if opt.done(&hud) {
+                break 'running;
+            }
+        }
+
+        let elapsed = rate_start.0.elapsed();
+        if elapsed >= RATE_INTERVAL {
+            hud.rate = (hud.generation - rate_start.1) as f64 / elapsed.as_secs_f64();
+            rate_start = (Instant::now(), hud.generation);
+        }
+        renderer.set_title(&format!(
+            "voyager | {} | generation {} | best length {:.1} | {:.1} gen/s{}",
+            opt.solver.name(),
+            hud.generation,
+            parents[0].length(),
+            hud.rate,
+            if paused { " | paused" } else { "" }
+        ))?;
+
+        // Wait out the rest of the frame, so as not to draw faster than the cap
+        if let Some(rest) = frame_time.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(rest);
+        }
     }
     pb.finish();
-    Ok(())
+    finish(&opt, &mut parents, view, &obstacles, &stats)
 }
@@ -1,62 +1,595 @@
-use euclid::Point2D;
+use crate::geo;
+use crate::terrain::Terrain;
+use image::GrayImage;
 use itertools::Itertools;
 use ordered_float::OrderedFloat;
 use rand::prelude::*;
+use rand_distr::Normal;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How many points the layouts that draw them at random may draw for each stop they need, before
+/// giving up on finding enough clear and unique ones
+const ATTEMPTS_PER_STOP: usize = 1000;
 
-pub struct MapSpace;
 pub type MapUnit = OrderedFloat<f64>;
-pub type MapPoint = Point2D<MapUnit, MapSpace>;
-pub type MapInner = Vec<MapPoint>;
 
-#[derive(Clone)]
-pub struct Map(pub MapInner);
+/// A type a position's coordinates can be stored as
+pub trait Coordinate: Copy + Eq + Ord + Hash + Default + Display + Send + Sync + 'static {
+    /// What squared differences between coordinates are summed in, wide enough that it neither
+    /// overflows nor loses precision
+    type Accumulator: Copy + Default + std::ops::Add<Output = Self::Accumulator>;
+
+    /// The coordinate closest to `value`
+    fn from_f64(value: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+
+    fn squared_difference(self, other: Self) -> Self::Accumulator;
+
+    /// The distance whose square is `sum`
+    fn root(sum: Self::Accumulator) -> f64;
+}
+
+impl Coordinate for OrderedFloat<f64> {
+    type Accumulator = f64;
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        OrderedFloat(value)
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self.into_inner()
+    }
+
+    #[inline(always)]
+    fn squared_difference(self, other: Self) -> f64 {
+        (self.into_inner() - other.into_inner()).powi(2)
+    }
+
+    #[inline(always)]
+    fn root(sum: f64) -> f64 {
+        sum.sqrt()
+    }
+}
+
+/// Half the size of the default, so more of a large map fits in the cache
+impl Coordinate for OrderedFloat<f32> {
+    type Accumulator = f64;
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        OrderedFloat(value as f32)
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        f64::from(self.into_inner())
+    }
+
+    #[inline(always)]
+    fn squared_difference(self, other: Self) -> f64 {
+        (self.to_f64() - other.to_f64()).powi(2)
+    }
+
+    #[inline(always)]
+    fn root(sum: f64) -> f64 {
+        sum.sqrt()
+    }
+}
+
+/// Integer coordinates have integer distances, rounded to the nearest one the way TSPLIB does for
+/// its EUC_2D instances, so tour lengths are exact and match published optima
+impl Coordinate for i32 {
+    type Accumulator = i64;
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value.round() as i32
+    }
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+
+    #[inline(always)]
+    fn squared_difference(self, other: Self) -> i64 {
+        (i64::from(self) - i64::from(other)).pow(2)
+    }
+
+    #[inline(always)]
+    fn root(sum: i64) -> f64 {
+        (sum as f64).sqrt().round()
+    }
+}
+
+/// What a map's coordinates are stored as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precision {
+    I32,
+    F32,
+    F64,
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i32" => Ok(Precision::I32),
+            "f32" => Ok(Precision::F32),
+            "f64" => Ok(Precision::F64),
+            _ => Err(format!("unknown precision {:?}, use i32, f32 or f64", s)),
+        }
+    }
+}
+
+/// A position with `D` coordinates, each stored as a `T`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point<const D: usize, T = MapUnit>(pub [T; D]);
+
+/// A position on a flat map, which is what obstacles are made of
+pub type MapPoint = Point<2>;
+pub type MapInner<P = MapPoint> = Vec<P>;
+/// What stops are called in the files they were loaded from
+pub type StopIds<P = MapPoint> = HashMap<P, String>;
+
+impl MapPoint {
+    pub fn new(x: MapUnit, y: MapUnit) -> Self {
+        Point([x, y])
+    }
+}
+
+impl<const D: usize, T: Coordinate> Default for Point<D, T> {
+    fn default() -> Self {
+        Point([T::default(); D])
+    }
+}
+
+impl<const D: usize, T: Coordinate> Display for Point<D, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "({})", self.0.iter().join(", "))
+    }
+}
+
+impl<const D: usize, T: Coordinate> Debug for Point<D, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// What maps need of their stops' positions, which lets them have any number of dimensions
+pub trait Stop: Copy + Eq + Ord + Hash + Default + Debug + Display + Send + Sync + 'static {
+    /// How many coordinates a position has
+    const DIMENSIONS: usize;
+
+    /// The coordinate along `axis`, which is zero past the last one
+    fn coord(&self, axis: usize) -> f64;
+
+    /// The position with the given coordinates. Missing ones are zero, extra ones are dropped.
+    fn from_coords(coords: &[f64]) -> Self;
 
-impl std::ops::Deref for Map {
-    type Target = MapInner;
+    /// The euclidean distance to `other`
+    fn distance(self, other: Self) -> f64;
+
+    fn coords(&self) -> Vec<f64> {
+        (0..Self::DIMENSIONS).map(|axis| self.coord(axis)).collect()
+    }
+
+    /// Where the position falls on the plane of the first two axes
+    #[inline(always)]
+    fn xy(&self) -> (f64, f64) {
+        (self.coord(0), self.coord(1))
+    }
+}
+
+impl<const D: usize, T: Coordinate> Stop for Point<D, T> {
+    const DIMENSIONS: usize = D;
+
+    #[inline(always)]
+    fn coord(&self, axis: usize) -> f64 {
+        self.0.get(axis).map_or(0.0, |c| c.to_f64())
+    }
+
+    fn from_coords(coords: &[f64]) -> Self {
+        let mut point = Self::default();
+        for (coord, &value) in point.0.iter_mut().zip(coords) {
+            *coord = T::from_f64(value);
+        }
+        point
+    }
+
+    #[inline(always)]
+    fn distance(self, other: Self) -> f64 {
+        let sum = self
+            .0
+            .iter()
+            .zip(&other.0)
+            .fold(T::Accumulator::default(), |sum, (a, b)| {
+                sum + a.squared_difference(*b)
+            });
+        T::root(sum)
+    }
+}
+
+pub struct Map<P = MapPoint>(pub MapInner<P>);
+
+impl<P: Clone> Clone for Map<P> {
+    fn clone(&self) -> Self {
+        Map(self.0.clone())
+    }
+
+    /// Reuses the storage of this map, so overwriting one map with another doesn't allocate
+    fn clone_from(&mut self, source: &Self) {
+        self.0.clone_from(&source.0);
+    }
+}
+
+impl<P> std::ops::Deref for Map<P> {
+    type Target = MapInner<P>;
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-impl std::ops::DerefMut for Map {
+impl<P> std::ops::DerefMut for Map<P> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
 
-impl From<MapInner> for Map {
-    fn from(inner: MapInner) -> Self {
+/// Parses a pair of numbers written as `x,y`
+pub fn parse_pair(s: &str) -> Result<(f64, f64), Box<dyn std::error::Error>> {
+    let (x, y) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected a pair like x,y, got {:?}", s))?;
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+/// Parses a point written as its comma separated coordinates, which have to be as many as it has
+/// dimensions
+pub fn parse_point<P: Stop>(s: &str) -> Result<P, Box<dyn std::error::Error>> {
+    let coords = s
+        .split(',')
+        .map(|coord| coord.trim().parse())
+        .collect::<Result<Vec<f64>, _>>()?;
+    if coords.len() != P::DIMENSIONS {
+        return Err(format!(
+            "expected a point with {} coordinates, got {:?}",
+            P::DIMENSIONS,
+            s
+        )
+        .into());
+    }
+    Ok(P::from_coords(&coords))
+}
+
+/// A change to the stops of a map
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit<P = MapPoint> {
+    Add(P),
+    Remove(P),
+    Move { from: P, to: P },
+}
+
+/// Keeps a table of per-stop attributes in step with an edit, new stops get whatever `new` gives
+pub fn edit_table<P: Stop, V>(table: &mut HashMap<P, V>, edit: Edit<P>, new: impl FnOnce() -> V) {
+    match edit {
+        Edit::Add(point) => {
+            table.insert(point, new());
+        }
+        Edit::Remove(point) => {
+            table.remove(&point);
+        }
+        Edit::Move { from, to } => {
+            if let Some(value) = table.remove(&from) {
+                table.insert(to, value);
+            }
+        }
+    }
+}
+
+impl<P: Stop> Map<P> {
+    /// Applies an edit. New stops go second, so neither end of the map (where the depots are
+    /// pinned) changes.
+    pub fn edit(&mut self, edit: Edit<P>) {
+        match edit {
+            Edit::Add(point) => {
+                let idx = 1.min(self.len());
+                self.insert(idx, point)
+            }
+            Edit::Remove(point) => self.retain(|pt| *pt != point),
+            Edit::Move { from, to } => self
+                .iter_mut()
+                .filter(|pt| **pt == from)
+                .for_each(|pt| *pt = to),
+        }
+    }
+
+    /// The corners of the map's bounding box, the lowest and highest coordinates along each axis
+    pub fn bounds(&self) -> (P, P) {
+        let (min, max): (Vec<f64>, Vec<f64>) = (0..P::DIMENSIONS)
+            .map(|axis| {
+                self.iter()
+                    .map(|pt| pt.coord(axis))
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), c| {
+                        (min.min(c), max.max(c))
+                    })
+            })
+            .unzip();
+        (P::from_coords(&min), P::from_coords(&max))
+    }
+
+    /// Moves the stop at `start` to the front of the map, and the one at `end` (if any) to the
+    /// back, which is where `TourMode::Depot` expects to find them.
+    pub fn pin(&mut self, start: usize, end: Option<usize>) {
+        let first = self[start];
+        let last = end.map(|idx| self[idx]).filter(|pt| *pt != first);
+        self.retain(|pt| *pt != first && Some(*pt) != last);
+        self.insert(0, first);
+        self.extend(last);
+    }
+}
+
+impl<P> From<MapInner<P>> for Map<P> {
+    fn from(inner: MapInner<P>) -> Self {
         Self(inner)
     }
 }
 
-impl std::fmt::Display for Map {
+impl<P: Stop> Display for Map<P> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let mut iter = self.iter();
-        let start = iter
-            .next()
-            .map(|p| format!("({}, {})", p.x, p.y))
-            .unwrap_or_else(|| "".to_string());
-        let repr = iter.fold(start, |acc, pt| format!("{} -> ({}, {})", acc, pt.x, pt.y));
-        write!(f, "{}", repr)
+        write!(f, "{}", self.iter().join(" -> "))
     }
 }
 
-impl std::fmt::Debug for Map {
+impl<P: Stop> FromStr for Map<P> {
+    type Err = Box<dyn std::error::Error>;
+
+    /// Parses stops the way they're displayed, `(x, y) -> (x, y) -> ...`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Map(Vec::new()));
+        }
+        s.split("->")
+            .map(|stop| {
+                let stop = stop.trim();
+                let coords = stop
+                    .strip_prefix('(')
+                    .and_then(|stop| stop.strip_suffix(')'))
+                    .ok_or_else(|| format!("expected a stop like (x, y), got {:?}", stop))?;
+                parse_point(coords)
+            })
+            .collect::<Result<_, _>>()
+            .map(Map)
+    }
+}
+
+impl<P: Debug> Debug for Map<P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{:?}", self.iter().format(" -> "))
+    }
+}
+
+/// How the cost of travelling between two stops is measured
+#[derive(Clone, Debug, Default)]
+pub enum Metric<P = MapPoint> {
+    /// As the crow flies
+    #[default]
+    Euclidean,
+    /// Along the shortest path around the terrain's obstacles
+    Terrain(Arc<Terrain<P>>),
+    /// Along the surface of the Earth in meters, taking the first two coordinates for longitude
+    /// and latitude in degrees
+    Haversine,
+}
+
+impl<P: Stop> Metric<P> {
+    #[inline]
+    pub fn distance(&self, a: P, b: P) -> f64 {
+        match self {
+            Metric::Euclidean => a.distance(b),
+            Metric::Terrain(terrain) => terrain.distance(a, b),
+            Metric::Haversine => geo::haversine(a.xy(), b.xy()),
+        }
+    }
+
+    /// Total distance travelled visiting `stops` in order
+    pub fn length(&self, stops: impl IntoIterator<Item = P>) -> f64 {
+        stops
+            .into_iter()
+            .tuple_windows()
+            .map(|(a, b)| self.distance(a, b))
+            .sum()
+    }
+
+    /// The polyline travelled between two stops, including both of them
+    pub fn path(&self, a: P, b: P) -> Vec<P> {
+        match self {
+            Metric::Euclidean | Metric::Haversine => vec![a, b],
+            Metric::Terrain(terrain) => terrain.path(a, b),
+        }
     }
 }
 
-pub fn random_map(width: u32, height: u32, entities: usize) -> Map {
-    let mut rng = thread_rng();
-    std::iter::repeat((width, height))
-        .map(|(x, y)| (rng.gen_range(0..x), rng.gen_range(0..y)))
-        .unique()
-        .take(entities)
-        .map(|(x, y)| (x as f64, y as f64))
-        .map(|(x, y)| (MapUnit::from(x), MapUnit::from(y)))
-        .map(|(x, y)| MapPoint::new(x, y))
-        .collect::<MapInner>()
-        .into()
+/// How the stops of a random map are laid out
+#[derive(Clone, Debug)]
+pub enum Generator {
+    /// Uniformly at random over the whole map
+    Uniform,
+    /// Gaussian clusters around `count` random centers, with a standard deviation of `spread`
+    /// times the map's shorter side
+    Clusters { count: usize, spread: f64 },
+    /// Evenly spaced around a circle, where the optimal tour is known to be its perimeter
+    Circle,
+    /// On a regular grid covering the map
+    Grid,
+    /// Spread over `count` concentric rings, with gaussian noise of `noise` times the map's
+    /// shorter side
+    Rings { count: usize, noise: f64 },
+    /// Stippled from a grayscale image stretched over the map, darker pixels get more stops
+    Image(Arc<GrayImage>),
+}
+
+impl Generator {
+    /// Loads an image to stipple from
+    pub fn image(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let image = image::open(path)?.into_luma8();
+        if image.pixels().all(|pixel| pixel.0[0] == u8::MAX) {
+            return Err(format!("{} is blank, there's nothing to stipple", path.display()).into());
+        }
+        Ok(Generator::Image(Arc::new(image)))
+    }
+
+    /// Generates a map of `entities` unique stops within `width` by `height`, skipping wherever
+    /// `clear` is false. The structured layouts don't replace skipped stops, so they may end up
+    /// with fewer, the random ones fail if they can't find room for all of them. Stops are laid
+    /// out across the first two axes, and spread uniformly along any others as deep as the map's
+    /// shorter side.
+    pub fn generate<P: Stop, R: Rng>(
+        &self,
+        width: u32,
+        height: u32,
+        entities: usize,
+        rng: &mut R,
+        clear: impl Fn(&P) -> bool,
+    ) -> Result<Map<P>, Box<dyn std::error::Error>> {
+        let random = matches!(
+            self,
+            Generator::Uniform | Generator::Clusters { .. } | Generator::Image(_)
+        );
+        let (w, h) = (f64::from(width), f64::from(height));
+        let side = w.min(h);
+        // Flat maps leave the generator's sequence alone, so seeds keep giving the same maps
+        let mut depth = (P::DIMENSIONS > 2).then(|| StdRng::from_rng(&mut *rng).unwrap());
+        let center = (w / 2.0, h / 2.0);
+        let clamp = |(x, y): (f64, f64)| (x.clamp(0.0, w - 1.0), y.clamp(0.0, h - 1.0));
+        let points: Box<dyn Iterator<Item = (f64, f64)> + '_> = match self {
+            Generator::Uniform => Box::new(
+                std::iter::repeat((width, height))
+                    .map(|(x, y)| (rng.gen_range(0..x), rng.gen_range(0..y)))
+                    .map(|(x, y)| (f64::from(x), f64::from(y))),
+            ),
+            Generator::Clusters { count, spread } => {
+                let centers: Vec<(f64, f64)> = (0..*count)
+                    .map(|_| (rng.gen_range(0.1..0.9) * w, rng.gen_range(0.1..0.9) * h))
+                    .collect();
+                let noise = Normal::new(0.0, spread * side).unwrap();
+                Box::new(std::iter::repeat_with(move || {
+                    let (x, y) = centers[rng.gen_range(0..centers.len())];
+                    clamp((x + noise.sample(rng), y + noise.sample(rng)))
+                }))
+            }
+            Generator::Circle => {
+                let radius = 0.45 * side;
+                Box::new((0..entities).map(move |idx| {
+                    let angle = std::f64::consts::TAU * idx as f64 / entities as f64;
+                    (
+                        center.0 + radius * angle.cos(),
+                        center.1 + radius * angle.sin(),
+                    )
+                }))
+            }
+            Generator::Grid => {
+                let columns = ((entities as f64 * w / h).sqrt().ceil() as usize).max(1);
+                let rows = entities.div_ceil(columns);
+                let (dx, dy) = (w / columns as f64, h / rows as f64);
+                Box::new((0..entities).map(move |idx| {
+                    let (column, row) = ((idx % columns) as f64, (idx / columns) as f64);
+                    ((column + 0.5) * dx, (row + 0.5) * dy)
+                }))
+            }
+            Generator::Rings { count, noise } => {
+                let count = *count;
+                let radius = 0.45 * side;
+                let noise = Normal::new(0.0, noise * side).unwrap();
+                // Each ring gets stops in proportion to its circumference, so they're equally dense
+                let total: usize = (1..=count).sum();
+                Box::new((0..entities).map(move |idx| {
+                    let position = idx as f64 * total as f64 / entities as f64;
+                    let ring = (1..=count)
+                        .scan(0.0, |seen, ring| {
+                            *seen += ring as f64;
+                            Some((ring, *seen))
+                        })
+                        .find(|(_, seen)| position < *seen)
+                        .map_or(count, |(ring, _)| ring);
+                    let r = radius * ring as f64 / count as f64;
+                    let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+                    clamp((
+                        center.0 + r * angle.cos() + noise.sample(rng),
+                        center.1 + r * angle.sin() + noise.sample(rng),
+                    ))
+                }))
+            }
+            Generator::Image(image) => {
+                let (iw, ih) = image.dimensions();
+                Box::new(std::iter::repeat_with(move || loop {
+                    let (x, y) = (rng.gen_range(0.0..w), rng.gen_range(0.0..h));
+                    let pixel = image.get_pixel(
+                        ((x / w * f64::from(iw)) as u32).min(iw - 1),
+                        ((y / h * f64::from(ih)) as u32).min(ih - 1),
+                    );
+                    let darkness = 1.0 - f64::from(pixel.0[0]) / f64::from(u8::MAX);
+                    if rng.gen::<f64>() < darkness {
+                        break (x, y);
+                    }
+                }))
+            }
+        };
+
+        let map: Map<P> = points
+            .take(entities.saturating_mul(ATTEMPTS_PER_STOP))
+            .map(|(x, y)| {
+                let mut coords = vec![x, y];
+                if let Some(depth) = &mut depth {
+                    coords.extend((2..P::DIMENSIONS).map(|_| depth.gen_range(0.0..side)));
+                }
+                P::from_coords(&coords)
+            })
+            .filter(|pt| clear(pt))
+            .unique()
+            .take(entities)
+            .collect::<MapInner<P>>()
+            .into();
+        if random && map.len() < entities {
+            let message = format!(
+                "only found room for {} of {} stops, the rest of the map is taken",
+                map.len(),
+                entities
+            );
+            return Err(message.into());
+        }
+        Ok(map)
+    }
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    /// Parses the name of a layout, with default parameters. Images have to be loaded with
+    /// `Generator::image`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Generator::Uniform),
+            "clusters" => Ok(Generator::Clusters {
+                count: 8,
+                spread: 0.05,
+            }),
+            "circle" => Ok(Generator::Circle),
+            "grid" => Ok(Generator::Grid),
+            "rings" => Ok(Generator::Rings {
+                count: 3,
+                noise: 0.01,
+            }),
+            _ => Err(format!("unknown map generator {:?}", s)),
+        }
+    }
 }
@@ -0,0 +1,97 @@
+use crate::chromosome::TourMode;
+use crate::map::{self, Edit, Map, MapPoint, Metric, Stop};
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A prize-collecting variant of the TSP: every stop has a reward, and the tour may be at most
+/// `budget` long, so only some of the stops can be visited.
+#[derive(Clone, Debug)]
+pub struct Orienteering<P = MapPoint> {
+    pub budget: f64,
+    pub max_reward: u32,
+    pub rewards: HashMap<P, u32>,
+}
+
+/// What a tour collects once decoded under the budget
+#[derive(Clone, Debug)]
+pub struct Visits {
+    pub reward: u32,
+    pub length: f64,
+    /// Whether each position of the tour is visited
+    pub visited: Vec<bool>,
+}
+
+impl<P: Stop> Orienteering<P> {
+    /// Gives each stop in `stops` a random reward of at most `max_reward`
+    pub fn random(map: &Map<P>, stops: Range<usize>, budget: f64, max_reward: u32) -> Self {
+        let mut rng = thread_rng();
+        let rewards = map[stops]
+            .iter()
+            .map(|&pt| (pt, rng.gen_range(1..=max_reward)))
+            .collect();
+        Orienteering {
+            budget,
+            max_reward,
+            rewards,
+        }
+    }
+
+    /// Keeps the rewards in step with an edit of the map, new stops get a random reward
+    pub fn edit(&mut self, edit: Edit<P>) {
+        let max_reward = self.max_reward;
+        map::edit_table(&mut self.rewards, edit, || {
+            thread_rng().gen_range(1..=max_reward)
+        });
+    }
+
+    #[inline]
+    pub fn reward(&self, point: &P) -> u32 {
+        self.rewards.get(point).copied().unwrap_or_default()
+    }
+
+    /// The reward available across the whole map
+    pub fn total(&self) -> u32 {
+        self.rewards.values().sum()
+    }
+
+    /// Walks `tour` in order, visiting each stop only if the tour can still be finished within the
+    /// budget afterwards. The tour's first stop (and end depot, if any) is always visited.
+    pub fn decode(&self, tour: &[P], mode: TourMode, metric: &Metric<P>) -> Visits {
+        let len = tour.len();
+        let first = tour[0];
+        let (end, stops) = match mode {
+            TourMode::Depot { end: true } => (Some(tour[len - 1]), 1..(len - 1)),
+            mode if mode.returns() => (Some(first), 1..len),
+            _ => (None, 1..len),
+        };
+
+        let mut visited = vec![false; len];
+        visited[0] = true;
+        let mut reward = self.reward(&first);
+        let mut length = 0.0;
+        let mut last = first;
+        for idx in stops {
+            let leg = metric.distance(last, tour[idx]);
+            let back = end.map_or(0.0, |end| metric.distance(tour[idx], end));
+            if length + leg + back <= self.budget {
+                visited[idx] = true;
+                reward += self.reward(&tour[idx]);
+                length += leg;
+                last = tour[idx];
+            }
+        }
+        if let Some(end) = end {
+            length += metric.distance(last, end);
+        }
+        if let TourMode::Depot { end: true } = mode {
+            visited[len - 1] = true;
+        }
+
+        Visits {
+            reward,
+            length,
+            visited,
+        }
+    }
+}
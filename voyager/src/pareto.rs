@@ -0,0 +1,237 @@
+use crate::chromosome::{self, Chromosome, Scratch};
+use crate::map::Stop;
+use ordered_float::OrderedFloat;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Something about a tour to keep as low as possible, alongside others it may trade off against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Objective {
+    /// Total distance travelled
+    Length,
+    /// Length of the longest leg between two stops
+    LongestLeg,
+    /// Total angle turned through at the stops, in radians
+    Turning,
+}
+
+impl Objective {
+    pub fn name(self) -> &'static str {
+        match self {
+            Objective::Length => "length",
+            Objective::LongestLeg => "longest-leg",
+            Objective::Turning => "turning",
+        }
+    }
+
+    /// Measures `tours`, the stops each vehicle visits in order, ends included
+    pub fn measure<P: Stop>(self, chromosome: &Chromosome<P>, tours: &[Vec<P>]) -> f64 {
+        let metric = &chromosome.problem.metric;
+        match self {
+            Objective::Length => tours
+                .iter()
+                .map(|tour| metric.length(tour.iter().copied()))
+                .sum(),
+            Objective::LongestLeg => tours
+                .iter()
+                .flat_map(|tour| tour.windows(2))
+                .map(|leg| metric.distance(leg[0], leg[1]))
+                .fold(0.0, f64::max),
+            Objective::Turning => tours
+                .iter()
+                .flat_map(|tour| tour.windows(3))
+                .map(|legs| {
+                    // The angle between the two legs' directions, which is 0 going straight on
+                    let (mut dot, mut first, mut second) = (0.0, 0.0, 0.0);
+                    for axis in 0..P::DIMENSIONS {
+                        let u = legs[1].coord(axis) - legs[0].coord(axis);
+                        let v = legs[2].coord(axis) - legs[1].coord(axis);
+                        dot += u * v;
+                        first += u * u;
+                        second += v * v;
+                    }
+                    let norms = (first * second).sqrt();
+                    if norms > 0.0 {
+                        (dot / norms).clamp(-1.0, 1.0).acos()
+                    } else {
+                        0.0
+                    }
+                })
+                .sum(),
+        }
+    }
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "length" => Ok(Objective::Length),
+            "longest-leg" => Ok(Objective::LongestLeg),
+            "turning" => Ok(Objective::Turning),
+            _ => Err(format!("unknown objective {:?}", s)),
+        }
+    }
+}
+
+/// Whether `a` is at least as good as `b` in every objective, and better in one
+pub fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a <= b) && a.iter().zip(b).any(|(a, b)| a < b)
+}
+
+/// Sorts the population into fronts of solutions that don't dominate one another, each front
+/// being dominated only by the ones before it. Returns each front's indices into the population.
+fn fronts<P: Stop>(population: &[Chromosome<P>]) -> Vec<Vec<usize>> {
+    let len = population.len();
+    // Which solutions each one dominates, and by how many it is dominated
+    let (dominated, mut counts): (Vec<Vec<usize>>, Vec<usize>) = (0..len)
+        .into_par_iter()
+        .map(|a| {
+            let mut dominated = Vec::new();
+            let mut count = 0;
+            for b in 0..len {
+                let (oa, ob) = (&population[a].objectives, &population[b].objectives);
+                if dominates(oa, ob) {
+                    dominated.push(b);
+                } else if dominates(ob, oa) {
+                    count += 1;
+                }
+            }
+            (dominated, count)
+        })
+        .unzip();
+
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..len).filter(|&idx| counts[idx] == 0).collect();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &a in &front {
+            for &b in &dominated[a] {
+                counts[b] -= 1;
+                if counts[b] == 0 {
+                    next.push(b);
+                }
+            }
+        }
+        fronts.push(std::mem::replace(&mut front, next));
+    }
+    fronts
+}
+
+/// How far apart the neighbors of each member of `front` are, summed over the objectives and
+/// relative to each one's range. The members at either end of any objective are infinitely far.
+fn crowding<P: Stop>(population: &[Chromosome<P>], front: &[usize]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    let objectives = population[front[0]].objectives.len();
+    let mut order: Vec<usize> = (0..front.len()).collect();
+    for objective in 0..objectives {
+        let value = |idx: usize| population[front[idx]].objectives[objective];
+        order.sort_by_key(|&idx| OrderedFloat(value(idx)));
+        let (first, last) = (order[0], order[order.len() - 1]);
+        let range = value(last) - value(first);
+        distances[first] = f64::INFINITY;
+        distances[last] = f64::INFINITY;
+        if range <= 0.0 {
+            continue;
+        }
+        for window in order.windows(3) {
+            distances[window[1]] += (value(window[2]) - value(window[0])) / range;
+        }
+    }
+    distances
+}
+
+/// Each solution's front, and its crowding distance within it
+fn rank<P: Stop>(population: &[Chromosome<P>]) -> Vec<(usize, f64)> {
+    let mut ranks = vec![(0, 0.0); population.len()];
+    for (rank, front) in fronts(population).iter().enumerate() {
+        for (&idx, distance) in front.iter().zip(crowding(population, front)) {
+            ranks[idx] = (rank, distance);
+        }
+    }
+    ranks
+}
+
+/// NSGA-II's crowded comparison: lower fronts first, then the less crowded
+fn crowded(a: (usize, f64), b: (usize, f64)) -> Ordering {
+    a.0.cmp(&b.0)
+        .then_with(|| OrderedFloat(b.1).cmp(&OrderedFloat(a.1)))
+}
+
+/// Breeds the next generation from `parents` into `children` with NSGA-II: parents are picked by
+/// binary tournaments on the crowded comparison, and the best half of parents and children by it
+/// survive into `parents`, the rest into `children` to be overwritten next generation.
+pub fn generation<P: Stop>(
+    parents: &mut Vec<Chromosome<P>>,
+    children: &mut Vec<Chromosome<P>>,
+    scratch: &mut Vec<Scratch<P>>,
+) {
+    let size = parents.len();
+    let ranks = rank(parents);
+    let tournament = |rng: &mut ThreadRng| {
+        let (a, b) = (rng.gen_range(0..size), rng.gen_range(0..size));
+        match crowded(ranks[a], ranks[b]) {
+            Ordering::Greater => b,
+            _ => a,
+        }
+    };
+
+    // Last generation's losers are overwritten, there are only new ones the first time
+    children.truncate(size / 2 * 2);
+    children.resize_with(size / 2 * 2, || parents[0].clone());
+    chromosome::breed(parents, children, scratch, |rng| {
+        (tournament(rng), tournament(rng))
+    });
+
+    parents.append(children);
+    let ranks = rank(parents);
+    let mut order: Vec<usize> = (0..parents.len()).collect();
+    order.sort_by(|&a, &b| crowded(ranks[a], ranks[b]));
+    let mut pool: Vec<Option<Chromosome<P>>> = parents.drain(..).map(Some).collect();
+    *parents = order
+        .into_iter()
+        .take(size)
+        .map(|idx| pool[idx].take().unwrap())
+        .collect();
+    children.extend(pool.into_iter().flatten());
+}
+
+/// The solutions no other one dominates, without duplicates, ordered by their first objective
+pub fn front<P: Stop>(population: &[Chromosome<P>]) -> Vec<Chromosome<P>> {
+    let mut front: Vec<Chromosome<P>> = fronts(population)
+        .first()
+        .map(|front| front.iter().map(|&idx| population[idx].clone()).collect())
+        .unwrap_or_default();
+    front.sort_by(|a, b| {
+        a.objectives
+            .iter()
+            .map(|&o| OrderedFloat(o))
+            .cmp(b.objectives.iter().map(|&o| OrderedFloat(o)))
+    });
+    front.dedup_by(|a, b| a.objectives == b.objectives);
+    front
+}
+
+/// Writes `front` to a CSV file, with a column for each objective and one for the tour
+pub fn save<P: Stop>(path: &Path, front: &[Chromosome<P>]) -> std::io::Result<()> {
+    let mut csv = String::new();
+    if let Some(first) = front.first() {
+        for objective in &first.problem.objectives {
+            csv += objective.name();
+            csv += ",";
+        }
+        csv += "tour\n";
+    }
+    for chromosome in front {
+        for value in &chromosome.objectives {
+            let _ = write!(csv, "{},", value);
+        }
+        let _ = writeln!(csv, "\"{}\"", chromosome.solution);
+    }
+    std::fs::write(path, csv)
+}
@@ -0,0 +1,781 @@
+use crate::chromosome::{Chromosome, TourMode};
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::map::{MapPoint, Metric, Stop};
+use crate::terrain::Polygon;
+use itertools::Itertools;
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    mouse::MouseButton,
+    pixels::Color,
+    rect::{Point, Rect},
+    render::{BlendMode, WindowCanvas},
+};
+use std::collections::HashMap;
+use std::iter::once;
+use std::str::FromStr;
+
+const STOP_SIZE: u32 = 5;
+/// Pixels left empty around the map when fitting it to the window
+const MARGIN: f64 = 20.0;
+/// How much one notch of the mouse wheel zooms in or out
+const ZOOM_STEP: f64 = 1.1;
+/// How far dragging the mouse one pixel turns the map, in radians
+const ROTATE_STEP: f64 = 0.01;
+/// How many pixels away from a stop a click may land and still pick it
+const PICK_RADIUS: i32 = 8;
+/// Size of a font pixel in the HUD, in screen pixels
+const HUD_SCALE: i32 = 2;
+/// Space between the HUD's panels and the edge of the window
+const HUD_PADDING: i32 = 10;
+const CHART_WIDTH: u32 = 300;
+const CHART_HEIGHT: u32 = 120;
+/// How many tours the overlay and grid views show to begin with
+const TOP_TOURS: usize = 8;
+/// Most samples kept of the best length, older ones get merged once it fills
+const HISTORY_LEN: usize = 1024;
+
+const COLOR_BACKGROUND: Color = Color::RGBA(10, 14, 20, 255);
+const COLOR_ENTITY: Color = Color::RGBA(230, 180, 80, 255);
+const COLOR_UNVISITED: Color = Color::RGBA(62, 75, 89, 255);
+const COLOR_PATH: Color = Color::RGBA(89, 194, 255, 255);
+/// Overlaid tours fade from the path's color for the best one to this for the worst
+const COLOR_WORST: Color = Color::RGBA(210, 166, 255, 64);
+const COLOR_DEPOT: Color = Color::RGBA(240, 113, 120, 255);
+const COLOR_OBSTACLE: Color = Color::RGBA(149, 230, 203, 255);
+const COLOR_PANEL: Color = Color::RGBA(0, 0, 0, 160);
+const COLOR_TEXT: Color = Color::RGBA(230, 225, 207, 255);
+const COLOR_CHART: Color = Color::RGBA(170, 217, 76, 255);
+/// Edges in the heatmap are drawn in this, more opaque the more of the population shares them
+const COLOR_HEAT: (u8, u8, u8) = (255, 143, 64);
+const COLOR_ROUTES: [Color; 8] = [
+    Color::RGBA(89, 194, 255, 255),
+    Color::RGBA(170, 217, 76, 255),
+    Color::RGBA(255, 143, 64, 255),
+    Color::RGBA(210, 166, 255, 255),
+    Color::RGBA(149, 230, 203, 255),
+    Color::RGBA(255, 180, 84, 255),
+    Color::RGBA(240, 113, 120, 255),
+    Color::RGBA(230, 225, 207, 255),
+];
+
+/// Maps coordinates in the map's space onto the window's pixels, and back. Maps with more than
+/// two dimensions are turned around their center and then projected onto the window, only their
+/// first three axes are shown.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    /// The point of the projected map shown at the center of the window
+    center: (f64, f64),
+    /// How far the map is turned around its vertical axis and then around its horizontal one
+    rotation: (f64, f64),
+    /// The point of the map it's turned around
+    pivot: [f64; 3],
+    /// Pixels per unit of distance
+    scale: f64,
+    /// Top left corner of the area drawn to, within the surface
+    origin: (i32, i32),
+    width: u32,
+    height: u32,
+    /// Whether the second axis points up the window, like latitudes on a map, rather than down
+    /// like pixels
+    pub north_up: bool,
+}
+
+impl Viewport {
+    pub fn new(width: u32, height: u32) -> Self {
+        Viewport {
+            center: (0.0, 0.0),
+            rotation: (0.0, 0.0),
+            pivot: [0.0; 3],
+            scale: 1.0,
+            origin: (0, 0),
+            width,
+            height,
+            north_up: false,
+        }
+    }
+
+    /// A viewport that only draws to `area` of the surface
+    pub fn area(area: Rect) -> Self {
+        Viewport {
+            origin: (area.x(), area.y()),
+            ..Viewport::new(area.width(), area.height())
+        }
+    }
+
+    /// Turns a point in the map's space around the pivot, and drops its depth
+    fn project(&self, point: &impl Stop) -> (f64, f64) {
+        let [x, y, z] = [0, 1, 2].map(|axis| point.coord(axis) - self.pivot[axis]);
+        let (yaw, pitch) = (self.rotation.0.sin_cos(), self.rotation.1.sin_cos());
+        let (x, z) = (x * yaw.1 + z * yaw.0, z * yaw.1 - x * yaw.0);
+        let y = y * pitch.1 - z * pitch.0;
+        let y = y + self.pivot[1];
+        (x + self.pivot[0], if self.north_up { -y } else { y })
+    }
+
+    /// The point of the map that projects onto (x, y) at the same depth as the pivot
+    fn unproject<P: Stop>(&self, (x, y): (f64, f64)) -> P {
+        let y = if self.north_up { -y } else { y };
+        let (x, y) = (x - self.pivot[0], y - self.pivot[1]);
+        let (yaw, pitch) = (self.rotation.0.sin_cos(), self.rotation.1.sin_cos());
+        let (y, z) = (y * pitch.1, -y * pitch.0);
+        let (x, z) = (x * yaw.1 - z * yaw.0, x * yaw.0 + z * yaw.1);
+        P::from_coords(&[x + self.pivot[0], y + self.pivot[1], z + self.pivot[2]])
+    }
+
+    /// Turns the map by `yaw` radians around its vertical axis and `pitch` around its horizontal
+    /// one
+    pub fn rotate(&mut self, yaw: f64, pitch: f64) {
+        self.rotation.0 += yaw;
+        self.rotation.1 += pitch;
+    }
+
+    /// Centers the view on the bounding box of `points`, zooming so it fills the window
+    pub fn fit<'a, P: Stop>(&mut self, points: impl IntoIterator<Item = &'a P>) {
+        let points: Vec<&P> = points.into_iter().collect();
+        for axis in 0..3 {
+            let coords = points.iter().map(|pt| pt.coord(axis));
+            let (min, max) = coords.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), c| {
+                (min.min(c), max.max(c))
+            });
+            if min <= max {
+                self.pivot[axis] = (min + max) / 2.0;
+            }
+        }
+        let bounds = points.into_iter().fold(None, |bounds, pt| {
+            let (x, y) = self.project(pt);
+            Some(match bounds {
+                None => (x, y, x, y),
+                Some((min_x, min_y, max_x, max_y)) => {
+                    (x.min(min_x), y.min(min_y), x.max(max_x), y.max(max_y))
+                }
+            })
+        });
+        let (min_x, min_y, max_x, max_y) = match bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        self.center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+        let usable_width = (f64::from(self.width) - 2.0 * MARGIN).max(1.0);
+        let usable_height = (f64::from(self.height) - 2.0 * MARGIN).max(1.0);
+        self.scale =
+            (usable_width / (max_x - min_x).max(1.0)).min(usable_height / (max_y - min_y).max(1.0));
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn to_screen(self, point: impl Stop) -> Point {
+        let (x, y) = self.project(&point);
+        let x = (x - self.center.0) * self.scale + f64::from(self.width) / 2.0;
+        let y = (y - self.center.1) * self.scale + f64::from(self.height) / 2.0;
+        Point::new(
+            self.origin.0 + x.round() as i32,
+            self.origin.1 + y.round() as i32,
+        )
+    }
+
+    /// Where the pixel (x, y) falls on the projected map
+    fn to_projected(self, x: i32, y: i32) -> (f64, f64) {
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        let x = (f64::from(x) - f64::from(self.width) / 2.0) / self.scale + self.center.0;
+        let y = (f64::from(y) - f64::from(self.height) / 2.0) / self.scale + self.center.1;
+        (x, y)
+    }
+
+    /// The point of the map under the pixel (x, y), at the same depth as the map's center
+    pub fn to_world<P: Stop>(self, x: i32, y: i32) -> P {
+        self.unproject(self.to_projected(x, y))
+    }
+
+    /// The point shown closest to the pixel (x, y), if any is close enough to click on
+    pub fn pick<'a, P: Stop>(
+        self,
+        points: impl IntoIterator<Item = &'a P>,
+        x: i32,
+        y: i32,
+    ) -> Option<P> {
+        points
+            .into_iter()
+            .map(|pt| {
+                let screen = self.to_screen(*pt);
+                let (dx, dy) = (screen.x() - x, screen.y() - y);
+                (dx * dx + dy * dy, *pt)
+            })
+            .filter(|(dist, _)| *dist <= PICK_RADIUS * PICK_RADIUS)
+            .min_by_key(|(dist, _)| *dist)
+            .map(|(_, pt)| pt)
+    }
+
+    /// Zooms by `factor`, keeping the map under the pixel (x, y) in place
+    pub fn zoom(&mut self, factor: f64, x: i32, y: i32) {
+        let anchor = self.to_projected(x, y);
+        self.scale *= factor;
+        let moved = self.to_projected(x, y);
+        self.center.0 += anchor.0 - moved.0;
+        self.center.1 += anchor.1 - moved.1;
+    }
+
+    /// Moves the map by (dx, dy) pixels
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.center.0 -= f64::from(dx) / self.scale;
+        self.center.1 -= f64::from(dy) / self.scale;
+    }
+}
+
+/// The best tour length over a run, downsampled so it never takes more than `HISTORY_LEN`
+/// samples. Each sample is the best of `stride` consecutive generations.
+#[derive(Clone, Debug)]
+pub struct History {
+    samples: Vec<f64>,
+    stride: usize,
+    /// How many generations the sample being gathered covers so far, and their best
+    partial: (usize, f64),
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            samples: Vec::with_capacity(HISTORY_LEN),
+            stride: 1,
+            partial: (0, f64::INFINITY),
+        }
+    }
+}
+
+impl History {
+    pub fn push(&mut self, length: f64) {
+        self.partial = (self.partial.0 + 1, self.partial.1.min(length));
+        if self.partial.0 < self.stride {
+            return;
+        }
+        self.samples.push(self.partial.1);
+        self.partial = (0, f64::INFINITY);
+        if self.samples.len() == HISTORY_LEN {
+            self.samples = self
+                .samples
+                .chunks(2)
+                .map(|pair| pair.iter().copied().fold(f64::INFINITY, f64::min))
+                .collect();
+            self.stride *= 2;
+        }
+    }
+}
+
+/// Figures shown in the on-canvas overlay
+#[derive(Clone, Debug, Default)]
+pub struct Hud {
+    pub generation: u64,
+    /// How many solutions have been scored so far
+    pub evaluations: u64,
+    /// Generations per second
+    pub rate: f64,
+    pub paused: bool,
+    pub best: f64,
+    pub mean: f64,
+    pub history: History,
+}
+
+/// Something scenes can be drawn on, the window or an exported image
+pub trait Surface {
+    fn size(&self) -> (u32, u32);
+    fn set_color(&mut self, color: Color);
+    /// Fills the whole surface with the current color
+    fn clear(&mut self);
+    fn fill_rects(&mut self, rects: &[Rect]) -> Result<(), String>;
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String>;
+
+    fn fill_rect(&mut self, rect: Rect) -> Result<(), String> {
+        self.fill_rects(&[rect])
+    }
+}
+
+impl Surface for WindowCanvas {
+    fn size(&self) -> (u32, u32) {
+        self.output_size().unwrap_or((0, 0))
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.set_draw_color(color);
+    }
+
+    fn clear(&mut self) {
+        WindowCanvas::clear(self);
+    }
+
+    fn fill_rects(&mut self, rects: &[Rect]) -> Result<(), String> {
+        WindowCanvas::fill_rects(self, rects)
+    }
+
+    fn draw_lines(&mut self, points: &[Point]) -> Result<(), String> {
+        WindowCanvas::draw_lines(self, points)
+    }
+}
+
+/// Which of the population's tours are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum View {
+    /// Only the best tour
+    Best,
+    /// The best `n` tours over each other, fading with their rank
+    Overlay(usize),
+    /// The best `n` tours side by side
+    Grid(usize),
+}
+
+impl View {
+    /// The view after this one, cycling through them all
+    pub fn next(self) -> Self {
+        match self {
+            View::Best => View::Overlay(TOP_TOURS),
+            View::Overlay(n) => View::Grid(n),
+            View::Grid(_) => View::Best,
+        }
+    }
+
+    /// The same view with `delta` more or fewer tours, though never less than two
+    pub fn resize(self, delta: isize) -> Self {
+        let resize = |n: usize| n.saturating_add_signed(delta).max(2);
+        match self {
+            View::Best => View::Best,
+            View::Overlay(n) => View::Overlay(resize(n)),
+            View::Grid(n) => View::Grid(resize(n)),
+        }
+    }
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    /// Parses the name of a view, which shows the default number of tours
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "best" => Ok(View::Best),
+            "overlay" => Ok(View::Overlay(TOP_TOURS)),
+            "grid" => Ok(View::Grid(TOP_TOURS)),
+            _ => Err(format!("unknown view {:?}", s)),
+        }
+    }
+}
+
+/// Color of the tour of the given rank, out of `count` overlaid ones
+fn rank_color(rank: usize, count: usize) -> Color {
+    let t = rank as f64 / (count.max(2) - 1) as f64;
+    let lerp = |a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * t).round() as u8;
+    Color::RGBA(
+        lerp(COLOR_PATH.r, COLOR_WORST.r),
+        lerp(COLOR_PATH.g, COLOR_WORST.g),
+        lerp(COLOR_PATH.b, COLOR_WORST.b),
+        lerp(COLOR_PATH.a, COLOR_WORST.a),
+    )
+}
+
+/// Everything drawn in a frame
+pub struct Scene<'a, P = MapPoint> {
+    /// The solutions, best first
+    pub population: &'a [Chromosome<P>],
+    pub view: View,
+    pub hud: Option<&'a Hud>,
+    /// Whether to shade edges beneath the tours by how much of the population uses them
+    pub heatmap: bool,
+    pub obstacles: &'a [Polygon],
+    /// Vertices of the obstacle being placed, if any
+    pub placing: Option<&'a [MapPoint]>,
+}
+
+/// Draws a scene onto any surface through a viewport
+struct Painter<'a, S> {
+    surface: &'a mut S,
+    viewport: Viewport,
+}
+
+impl<S: Surface> Painter<'_, S> {
+    fn stop(&self, point: &impl Stop) -> Rect {
+        Rect::from_center(self.viewport.to_screen(*point), STOP_SIZE, STOP_SIZE)
+    }
+
+    /// Plots the path through `stops`, following whatever detours the metric takes between them
+    fn path<P: Stop>(&mut self, metric: &Metric<P>, stops: &[P]) -> Result<(), String> {
+        let viewport = self.viewport;
+        let surface = &mut *self.surface;
+        stops.iter().tuple_windows().try_for_each(|(a, b)| {
+            let line: Vec<_> = metric
+                .path(*a, *b)
+                .into_iter()
+                .map(|pt| viewport.to_screen(pt))
+                .collect();
+            surface.draw_lines(line.as_slice())
+        })
+    }
+
+    /// Shades every edge of the population's tours by the share of tours that contain it
+    fn heatmap<P: Stop>(&mut self, population: &[Chromosome<P>]) -> Result<(), String> {
+        let mut counts: HashMap<(P, P), usize> = HashMap::new();
+        for chromosome in population {
+            let tour = &chromosome.solution;
+            let closing = Some(tour[0]).filter(|_| chromosome.problem.mode.returns());
+            for (a, b) in tour.iter().copied().chain(closing).tuple_windows() {
+                // Edges are undirected, so both directions count the same
+                let key = if a <= b { (a, b) } else { (b, a) };
+                *counts.entry(key).or_default() += 1;
+            }
+        }
+        let metric = match population.first() {
+            Some(chromosome) => chromosome.problem.metric.clone(),
+            None => return Ok(()),
+        };
+        let (r, g, b) = COLOR_HEAT;
+        for ((from, to), count) in counts {
+            let alpha = (255 * count / population.len()) as u8;
+            self.surface.set_color(Color::RGBA(r, g, b, alpha));
+            self.path(&metric, &[from, to])?;
+        }
+        Ok(())
+    }
+
+    /// A dark translucent panel for the HUD to draw on
+    fn panel(&mut self, rect: Rect) -> Result<(), String> {
+        self.surface.set_color(COLOR_PANEL);
+        self.surface.fill_rect(rect)
+    }
+
+    /// Draws the figures in the top left corner, and the chart of the best length in the bottom
+    /// left one
+    fn hud(&mut self, hud: &Hud) -> Result<(), String> {
+        let mut lines = vec![
+            format!("generation {}", hud.generation),
+            format!("{:.1} gen/s", hud.rate),
+            format!("{} evals", hud.evaluations),
+            format!("best {:.1}", hud.best),
+            format!("mean {:.1}", hud.mean),
+        ];
+        if hud.paused {
+            lines.push("paused".to_owned());
+        }
+        let line_height = (GLYPH_HEIGHT + 2) * HUD_SCALE;
+        let width = lines
+            .iter()
+            .map(|line| font::text_width(line, HUD_SCALE))
+            .max()
+            .unwrap_or(0);
+        let height = lines.len() as i32 * line_height;
+        self.panel(Rect::new(
+            HUD_PADDING,
+            HUD_PADDING,
+            (width + 2 * HUD_PADDING) as u32,
+            (height + 2 * HUD_PADDING) as u32,
+        ))?;
+        self.surface.set_color(COLOR_TEXT);
+        for (idx, line) in lines.iter().enumerate() {
+            let y = 2 * HUD_PADDING + idx as i32 * line_height;
+            font::draw_text(self.surface, line, 2 * HUD_PADDING, y, HUD_SCALE)?;
+        }
+
+        let samples = &hud.history.samples;
+        if samples.len() < 2 {
+            return Ok(());
+        }
+        let (_, surface_height) = self.surface.size();
+        let chart = Rect::new(
+            HUD_PADDING,
+            surface_height as i32 - HUD_PADDING - CHART_HEIGHT as i32,
+            CHART_WIDTH,
+            CHART_HEIGHT,
+        );
+        self.panel(chart)?;
+        let (min, max) = samples
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &s| {
+                (min.min(s), max.max(s))
+            });
+        let inner = (
+            f64::from(CHART_WIDTH) - 2.0 * f64::from(HUD_PADDING),
+            f64::from(CHART_HEIGHT) - 2.0 * f64::from(HUD_PADDING),
+        );
+        let line: Vec<_> = samples
+            .iter()
+            .enumerate()
+            .map(|(idx, &sample)| {
+                let x = idx as f64 / (samples.len() - 1) as f64 * inner.0;
+                let y = (max - sample) / (max - min).max(f64::EPSILON) * inner.1;
+                Point::new(
+                    chart.x() + HUD_PADDING + x.round() as i32,
+                    chart.y() + HUD_PADDING + y.round() as i32,
+                )
+            })
+            .collect();
+        self.surface.set_color(COLOR_CHART);
+        self.surface.draw_lines(line.as_slice())
+    }
+
+    /// Plots the stops, graying out the ones the tour doesn't visit
+    fn stops<P: Stop>(&mut self, chromosome: &Chromosome<P>) -> Result<(), String> {
+        let visits = chromosome.visits();
+        for (idx, point) in chromosome.solution.iter().enumerate() {
+            let visited = visits.as_ref().is_none_or(|v| v.visited[idx]);
+            let color = if visited {
+                COLOR_ENTITY
+            } else {
+                COLOR_UNVISITED
+            };
+            self.surface.set_color(color);
+            self.surface.fill_rect(self.stop(point))?;
+        }
+        Ok(())
+    }
+
+    /// Plots a tour in `color`, or each of its vehicle's routes in their own color if it's `None`
+    fn tour<P: Stop>(
+        &mut self,
+        chromosome: &Chromosome<P>,
+        color: Option<Color>,
+    ) -> Result<(), String> {
+        let problem = &chromosome.problem;
+        if let Some(routes) = chromosome.routes() {
+            // All of the routes start and end at the depot
+            let depot = chromosome.solution[0];
+            for (route, palette) in routes.iter().zip(COLOR_ROUTES.iter().cycle()) {
+                self.surface.set_color(color.unwrap_or(*palette));
+                let stops: Vec<_> = once(depot)
+                    .chain(route.iter().copied())
+                    .chain(once(depot))
+                    .collect();
+                self.path(&problem.metric, &stops)?;
+            }
+            return Ok(());
+        }
+
+        // Plot the paths, and the closing one unless the tour is open
+        let visits = chromosome.visits();
+        self.surface.set_color(color.unwrap_or(COLOR_PATH));
+        let mut stops: Vec<_> = chromosome
+            .solution
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| visits.as_ref().is_none_or(|v| v.visited[*idx]))
+            .map(|(_, point)| *point)
+            .collect();
+        if problem.mode.returns() {
+            stops.push(stops[0]);
+        }
+        self.path(&problem.metric, &stops)
+    }
+
+    /// Plots the obstacles, and whichever one is being placed
+    fn obstacles<P>(&mut self, scene: &Scene<P>) -> Result<(), String> {
+        self.surface.set_color(COLOR_OBSTACLE);
+        for polygon in scene.obstacles {
+            let outline: Vec<_> = polygon.0.iter().chain(&polygon.0[..1]).copied().collect();
+            self.path(&Metric::Euclidean, &outline)?;
+        }
+        if let Some(vertices) = scene.placing {
+            self.path(&Metric::Euclidean, vertices)?;
+            for vertex in vertices {
+                self.surface.fill_rect(self.stop(vertex))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn depots<P: Stop>(&mut self, chromosome: &Chromosome<P>) -> Result<(), String> {
+        if let TourMode::Depot { end } = chromosome.problem.mode {
+            self.surface.set_color(COLOR_DEPOT);
+            self.surface.fill_rect(self.stop(&chromosome.solution[0]))?;
+            if end {
+                self.surface
+                    .fill_rect(self.stop(chromosome.solution.last().unwrap()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Plots `tours` over each other with the best on top, or just the best one with its routes
+    /// told apart if there's only that
+    fn map<P: Stop>(&mut self, scene: &Scene<P>, tours: &[Chromosome<P>]) -> Result<(), String> {
+        let best = &tours[0];
+        self.stops(best)?;
+        if scene.heatmap {
+            self.heatmap(scene.population)?;
+        }
+        if tours.len() == 1 {
+            self.tour(best, None)?;
+        } else {
+            for (rank, chromosome) in tours.iter().enumerate().rev() {
+                self.tour(chromosome, Some(rank_color(rank, tours.len())))?;
+            }
+        }
+        self.obstacles(scene)?;
+        // Highlight the depots over everything else
+        self.depots(best)
+    }
+
+    /// Plots each of `tours` in its own cell of a grid, labelled with its rank and length
+    fn grid<P: Stop>(&mut self, scene: &Scene<P>, tours: &[Chromosome<P>]) -> Result<(), String> {
+        let (width, height) = self.surface.size();
+        let columns = (tours.len() as f64).sqrt().ceil().max(1.0) as u32;
+        let rows = (tours.len() as u32).div_ceil(columns);
+        let cell = (width / columns, height / rows.max(1));
+        for (idx, chromosome) in tours.iter().enumerate() {
+            let area = Rect::new(
+                (idx as u32 % columns * cell.0) as i32,
+                (idx as u32 / columns * cell.1) as i32,
+                cell.0,
+                cell.1,
+            );
+            // Every cell shows its tour from the same angle as the window
+            let mut viewport = Viewport {
+                rotation: self.viewport.rotation,
+                north_up: self.viewport.north_up,
+                ..Viewport::area(area)
+            };
+            viewport.fit(chromosome.solution.iter());
+            let mut painter = Painter {
+                surface: &mut *self.surface,
+                viewport,
+            };
+            painter.stops(chromosome)?;
+            painter.tour(chromosome, None)?;
+            painter.obstacles(scene)?;
+            painter.depots(chromosome)?;
+
+            self.surface.set_color(COLOR_UNVISITED);
+            self.surface.draw_lines(&[
+                area.top_left(),
+                area.top_right(),
+                area.bottom_right(),
+                area.bottom_left(),
+                area.top_left(),
+            ])?;
+            self.surface.set_color(COLOR_TEXT);
+            let label = format!("{} | {:.1}", idx + 1, chromosome.length());
+            font::draw_text(
+                self.surface,
+                &label,
+                area.x() + HUD_PADDING,
+                area.bottom() - HUD_PADDING - GLYPH_HEIGHT * HUD_SCALE,
+                HUD_SCALE,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn scene<P: Stop>(&mut self, scene: &Scene<P>) -> Result<(), String> {
+        self.surface.set_color(COLOR_BACKGROUND);
+        self.surface.clear();
+        match scene.view {
+            View::Best => self.map(scene, &scene.population[..1])?,
+            View::Overlay(n) => {
+                let n = n.clamp(1, scene.population.len());
+                self.map(scene, &scene.population[..n])?
+            }
+            View::Grid(n) => {
+                let n = n.clamp(1, scene.population.len());
+                self.grid(scene, &scene.population[..n])?
+            }
+        }
+        if let Some(hud) = scene.hud {
+            self.hud(hud)?;
+        }
+        Ok(())
+    }
+}
+
+/// Draws `scene` onto `surface`, placing the map through `viewport`
+pub fn paint<S: Surface, P: Stop>(
+    surface: &mut S,
+    viewport: Viewport,
+    scene: &Scene<P>,
+) -> Result<(), String> {
+    Painter { surface, viewport }.scene(scene)
+}
+
+pub struct Renderer {
+    canvas: WindowCanvas,
+    pub viewport: Viewport,
+    /// Whether the map has depth, so dragging with shift held turns it rather than panning
+    pub rotates: bool,
+    /// Last known position of the mouse, which zooming is centered on
+    cursor: (i32, i32),
+    panning: bool,
+    shift: bool,
+}
+
+impl Renderer {
+    pub fn new(mut canvas: WindowCanvas) -> Result<Self, String> {
+        canvas.set_blend_mode(BlendMode::Blend);
+        let (width, height) = canvas.output_size()?;
+        Ok(Renderer {
+            canvas,
+            viewport: Viewport::new(width, height),
+            rotates: false,
+            cursor: (0, 0),
+            panning: false,
+            shift: false,
+        })
+    }
+
+    /// Handles the events that move the view: the mouse wheel zooms, dragging with the middle
+    /// button pans (or turns the map with shift held, if it has depth), and resizing the window
+    /// keeps the map's center in place. Returns whether the event was one of those.
+    pub fn handle(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::MouseWheel { y, .. } => {
+                let (cx, cy) = self.cursor;
+                self.viewport.zoom(ZOOM_STEP.powi(y), cx, cy);
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Middle,
+                ..
+            } => self.panning = true,
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Middle,
+                ..
+            } => self.panning = false,
+            Event::MouseMotion {
+                x, y, xrel, yrel, ..
+            } => {
+                self.cursor = (x, y);
+                if self.panning && self.shift && self.rotates {
+                    self.viewport
+                        .rotate(f64::from(xrel) * ROTATE_STEP, f64::from(yrel) * ROTATE_STEP);
+                } else if self.panning {
+                    self.viewport.pan(xrel, yrel);
+                }
+                // Other handlers may care about where the mouse is too
+                return false;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::LShift | Keycode::RShift),
+                ..
+            }
+            | Event::KeyUp {
+                keycode: Some(Keycode::LShift | Keycode::RShift),
+                ..
+            } => {
+                self.shift = matches!(event, Event::KeyDown { .. });
+                return false;
+            }
+            Event::Window {
+                win_event: WindowEvent::SizeChanged(width, height),
+                ..
+            } => self.viewport.resize(width as u32, height as u32),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn set_title(&mut self, title: &str) -> Result<(), String> {
+        self.canvas
+            .window_mut()
+            .set_title(title)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn draw<P: Stop>(&mut self, scene: &Scene<P>) -> Result<(), String> {
+        paint(&mut self.canvas, self.viewport, scene)?;
+        self.canvas.present();
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::map::{self, Edit, Map, MapPoint, Metric, Stop};
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// When a stop can be served, and how long serving it takes. Service may not start before
+/// `ready`, and starting it after `due` makes the stop late.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeWindow {
+    pub ready: f64,
+    pub due: f64,
+    pub service: f64,
+}
+
+impl Default for TimeWindow {
+    fn default() -> Self {
+        TimeWindow {
+            ready: 0.0,
+            due: f64::INFINITY,
+            service: 0.0,
+        }
+    }
+}
+
+/// How tours that miss windows are dealt with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Every unit of lateness costs as much as `weight` units of distance
+    Penalty { weight: f64 },
+    /// Late stops are moved earlier in the tour before it's scored, whatever lateness is left
+    /// after that is penalized as above
+    Repair { weight: f64 },
+}
+
+impl Strategy {
+    #[inline]
+    pub fn weight(self) -> f64 {
+        match self {
+            Strategy::Penalty { weight } | Strategy::Repair { weight } => weight,
+        }
+    }
+}
+
+/// The time windows of a map's stops. Stops without one can be served at any time.
+#[derive(Clone, Debug)]
+pub struct Schedule<P = MapPoint> {
+    pub windows: HashMap<P, TimeWindow>,
+    pub strategy: Strategy,
+    /// Windows open at some point before this
+    horizon: f64,
+    width: f64,
+    service: f64,
+}
+
+/// A vehicle's progress through a route, assuming it travels one unit of distance per unit of time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Clock {
+    /// When the vehicle is done serving the last stop it arrived at
+    pub time: f64,
+    /// Sum of how late each stop so far was served
+    pub lateness: f64,
+    /// How many stops so far were served late
+    pub violations: usize,
+}
+
+impl<P: Stop> Schedule<P> {
+    /// Gives each stop in `stops` a window `width` wide, opening at some random time within the
+    /// expected length of a good tour through the whole map, as `metric` measures it.
+    pub fn random(
+        map: &Map<P>,
+        stops: Range<usize>,
+        width: f64,
+        service: f64,
+        strategy: Strategy,
+        metric: &Metric<P>,
+    ) -> Self {
+        // The volume of the map's bounding box, which is its area on a flat map. Each side is
+        // measured by the metric, as on a geographic map the coordinates are degrees.
+        let (min, max) = map.bounds();
+        let volume: f64 = (0..P::DIMENSIONS)
+            .map(|axis| {
+                let mut corner = min.coords();
+                corner[axis] = max.coord(axis);
+                metric.distance(min, P::from_coords(&corner)).max(1.0)
+            })
+            .product();
+        // The Beardwood–Halton–Hammersley estimate of an optimal tour's length, the constant is
+        // the one for flat maps but it's close enough in a few more dimensions
+        let dimensions = P::DIMENSIONS as f64;
+        let horizon = 0.7124
+            * (map.len() as f64).powf((dimensions - 1.0) / dimensions)
+            * volume.powf(1.0 / dimensions)
+            + map.len() as f64 * service;
+
+        let mut schedule = Schedule {
+            windows: HashMap::new(),
+            strategy,
+            horizon,
+            width,
+            service,
+        };
+        schedule.windows = map[stops]
+            .iter()
+            .map(|&pt| (pt, schedule.random_window()))
+            .collect();
+        schedule
+    }
+
+    fn random_window(&self) -> TimeWindow {
+        let ready = thread_rng().gen_range(0.0..self.horizon);
+        TimeWindow {
+            ready,
+            due: ready + self.width,
+            service: self.service,
+        }
+    }
+
+    /// Keeps the windows in step with an edit of the map, new stops get a random window
+    pub fn edit(&mut self, edit: Edit<P>) {
+        let window = self.random_window();
+        map::edit_table(&mut self.windows, edit, || window);
+    }
+
+    #[inline]
+    pub fn window(&self, point: &P) -> TimeWindow {
+        self.windows.get(point).copied().unwrap_or_default()
+    }
+
+    /// Serves `first` at time zero
+    #[inline]
+    pub fn start(&self, first: P) -> Clock {
+        self.arrive(Clock::default(), first, 0.0)
+    }
+
+    /// Travels from `from`, which was just served, to `to` and serves it
+    #[inline]
+    pub fn travel(&self, clock: Clock, metric: &Metric<P>, from: P, to: P) -> Clock {
+        let arrival = clock.time + metric.distance(from, to);
+        self.arrive(clock, to, arrival)
+    }
+
+    #[inline]
+    fn arrive(&self, mut clock: Clock, at: P, arrival: f64) -> Clock {
+        let window = self.window(&at);
+        if arrival > window.due {
+            clock.lateness += arrival - window.due;
+            clock.violations += 1;
+        }
+        clock.time = arrival.max(window.ready) + window.service;
+        clock
+    }
+
+    /// Walks `stops` in order, starting at time zero
+    pub fn walk(&self, metric: &Metric<P>, stops: impl IntoIterator<Item = P>) -> Clock {
+        let mut stops = stops.into_iter();
+        let first = match stops.next() {
+            Some(first) => first,
+            None => return Clock::default(),
+        };
+        stops
+            .fold((self.start(first), first), |(clock, from), to| {
+                (self.travel(clock, metric, from, to), to)
+            })
+            .0
+    }
+}
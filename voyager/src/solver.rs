@@ -0,0 +1,161 @@
+use crate::annealing::Annealing;
+use crate::chromosome::{Chromosome, Scratch};
+use crate::colony::Colony;
+use crate::map::Stop;
+use crate::stats::Stats;
+use crate::steady::SteadyState;
+use crate::tabu::Tabu;
+use rand::prelude::*;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Which metaheuristic searches for tours
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Solver {
+    /// The genetic algorithm, breeding a population of tours
+    Genetic,
+    /// Simulated annealing, wandering from one tour to the next and accepting worse ones less and
+    /// less often as it cools
+    Annealing,
+    /// Ant colony optimization, building tours stop by stop along the edges good tours left
+    /// pheromone on
+    Colony,
+    /// Tabu search, always taking the best of the moves nearby while forbidding ones that undo
+    /// recent moves
+    Tabu,
+}
+
+impl Solver {
+    pub fn name(self) -> &'static str {
+        match self {
+            Solver::Genetic => "ga",
+            Solver::Annealing => "sa",
+            Solver::Colony => "aco",
+            Solver::Tabu => "tabu",
+        }
+    }
+}
+
+impl FromStr for Solver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ga" => Ok(Solver::Genetic),
+            "sa" => Ok(Solver::Annealing),
+            "aco" => Ok(Solver::Colony),
+            "tabu" => Ok(Solver::Tabu),
+            _ => Err(format!("unknown solver {:?}, use ga, sa, aco or tabu", s)),
+        }
+    }
+}
+
+/// What a solver keeps between generations, besides the population
+#[derive(Clone, Debug)]
+pub enum Search<P> {
+    /// The scratch buffers each thread breeds offspring with
+    Genetic(Vec<Scratch<P>>),
+    SteadyState(SteadyState<P>),
+    Annealing(Annealing),
+    Colony(Colony<P>),
+    Tabu(Tabu<P>),
+}
+
+impl<P: Stop> Search<P> {
+    pub fn new(solver: Solver) -> Self {
+        match solver {
+            Solver::Genetic => Search::Genetic(Vec::new()),
+            Solver::Annealing => Search::Annealing(Annealing::default()),
+            Solver::Colony => Search::Colony(Colony::default()),
+            Solver::Tabu => Search::Tabu(Tabu::default()),
+        }
+    }
+
+    /// What the GA's mutation operators did since this was last called, other solvers don't
+    /// have any
+    pub fn take_stats(&mut self) -> Option<Stats> {
+        let scratch = match self {
+            Search::Genetic(scratch) => scratch,
+            Search::SteadyState(steady) => &mut steady.scratch,
+            _ => return None,
+        };
+        let mut stats = Stats::default();
+        for scratch in scratch {
+            stats.merge(&std::mem::take(&mut scratch.stats));
+        }
+        Some(stats)
+    }
+}
+
+/// A small change to the free stretch of a tour, which the searches that go from one tour to a
+/// neighboring one try
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Move {
+    /// Reverses the stops between two positions, both included, like 2-opt does
+    Reverse(usize, usize),
+    /// Takes the stop at one position out and puts it back in at another
+    Relocate(usize, usize),
+}
+
+impl Move {
+    /// A random move within `free`, if it has enough stops for one to change anything
+    pub fn random(free: Range<usize>, rng: &mut impl Rng) -> Option<Self> {
+        if free.len() < 3 {
+            return None;
+        }
+        let a = rng.gen_range(free.clone());
+        let mut b = rng.gen_range(free.clone());
+        while a == b {
+            b = rng.gen_range(free.clone());
+        }
+        Some(if rng.gen_bool(0.5) {
+            Move::Reverse(a.min(b), a.max(b))
+        } else {
+            Move::Relocate(a, b)
+        })
+    }
+
+    /// A move that brings a random stop and one of its candidates together, by reversing the
+    /// stretch between them or by moving the candidate next to it
+    pub fn near<P: Stop>(
+        chromosome: &Chromosome<P>,
+        free: Range<usize>,
+        rng: &mut impl Rng,
+    ) -> Option<Self> {
+        let solution = &chromosome.solution;
+        if free.len() < 3 {
+            return None;
+        }
+        let a = rng.gen_range(free.clone());
+        let candidate = chromosome.problem.neighbors.of(solution[a]).choose(rng)?;
+        let b = free.start + solution[free].iter().position(|stop| stop == candidate)?;
+        let mv = match (rng.gen_bool(0.5), a < b) {
+            (true, true) => Move::Reverse(a + 1, b),
+            (true, false) => Move::Reverse(b + 1, a),
+            (false, true) => Move::Relocate(b, a + 1),
+            (false, false) => Move::Relocate(b, a),
+        };
+        // Stops that are already next to each other can't be brought any closer
+        Some(mv).filter(|_| a.abs_diff(b) > 1)
+    }
+
+    /// The positions the move takes stops from
+    pub fn ends(self) -> (usize, usize) {
+        match self {
+            Move::Reverse(a, b) | Move::Relocate(a, b) => (a, b),
+        }
+    }
+
+    /// The neighbor of `chromosome` the move leads to, scored against the same problem
+    pub fn apply<P: Stop>(self, chromosome: &Chromosome<P>) -> Chromosome<P> {
+        let mut solution = chromosome.solution.clone();
+        match self {
+            Move::Reverse(a, b) => solution[a..=b].reverse(),
+            Move::Relocate(from, to) => {
+                let stop = solution.remove(from);
+                solution.insert(to, stop);
+            }
+        }
+        Chromosome::new(solution, chromosome.problem.clone())
+    }
+}
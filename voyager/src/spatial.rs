@@ -0,0 +1,157 @@
+use crate::map::{MapPoint, Metric, Stop};
+use ordered_float::OrderedFloat;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// How many candidates each stop gets, the closest ones are the only ones local search tries to
+/// connect it to
+const CANDIDATES: usize = 8;
+/// Roughly how many points share a cell of the grid
+const POINTS_PER_CELL: f64 = 2.0;
+
+/// Buckets points into the square cells of a grid, so the ones close to a point can be found
+/// without looking at every other one. Cells only split the plane of the first two axes, which is
+/// still right in more dimensions since points are at least as far apart as they are on it.
+#[derive(Clone, Debug)]
+pub struct Grid<P = MapPoint> {
+    origin: (f64, f64),
+    /// Side of a cell
+    cell: f64,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<P>>,
+}
+
+impl<P: Stop> Grid<P> {
+    pub fn new(points: &[P]) -> Self {
+        let (min, max) = points.iter().fold(
+            (
+                (f64::INFINITY, f64::INFINITY),
+                (f64::NEG_INFINITY, f64::NEG_INFINITY),
+            ),
+            |(min, max), &pt| {
+                let (x, y) = pt.xy();
+                ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+            },
+        );
+        let (width, height) = ((max.0 - min.0).max(1.0), (max.1 - min.1).max(1.0));
+        let cell = (width * height * POINTS_PER_CELL / points.len().max(1) as f64).sqrt();
+        let columns = (width / cell).ceil() as usize + 1;
+        let rows = (height / cell).ceil() as usize + 1;
+        let mut grid = Grid {
+            origin: if points.is_empty() { (0.0, 0.0) } else { min },
+            cell,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+        };
+        for &point in points {
+            let idx = grid.index(grid.cell_of(point));
+            grid.cells[idx].push(point);
+        }
+        grid
+    }
+
+    /// The cell `point` falls in, points outside the grid fall in the closest cell along its edge
+    fn cell_of(&self, point: P) -> (usize, usize) {
+        let (x, y) = point.xy();
+        let column = ((x - self.origin.0) / self.cell).floor().max(0.0) as usize;
+        let row = ((y - self.origin.1) / self.cell).floor().max(0.0) as usize;
+        (column.min(self.columns - 1), row.min(self.rows - 1))
+    }
+
+    fn index(&self, (column, row): (usize, usize)) -> usize {
+        row * self.columns + column
+    }
+
+    /// The points in the cells `radius` cells away from `center`, along either axis
+    fn ring(&self, center: (usize, usize), radius: usize) -> impl Iterator<Item = &P> {
+        let (column, row) = (center.0 as isize, center.1 as isize);
+        let radius = radius as isize;
+        let (columns, rows) = (self.columns as isize, self.rows as isize);
+        (row - radius..=row + radius)
+            .flat_map(move |r| (column - radius..=column + radius).map(move |c| (c, r)))
+            .filter(move |&(c, r)| {
+                (c - column).abs().max((r - row).abs()) == radius
+                    && (0..columns).contains(&c)
+                    && (0..rows).contains(&r)
+            })
+            .flat_map(move |(c, r)| &self.cells[(r * columns + c) as usize])
+    }
+
+    pub fn remove(&mut self, point: P) {
+        let idx = self.index(self.cell_of(point));
+        if let Some(pos) = self.cells[idx].iter().position(|&pt| pt == point) {
+            self.cells[idx].swap_remove(pos);
+        }
+    }
+
+    /// The `k` points closest to `point`, closest first, leaving out `point` itself
+    pub fn k_nearest(&self, point: P, k: usize) -> Vec<P> {
+        let center = self.cell_of(point);
+        let mut found: Vec<(f64, P)> = Vec::with_capacity(k + 1);
+        for radius in 0..self.columns.max(self.rows) {
+            // Anything further out is at least this far away
+            let reach = radius.saturating_sub(1) as f64 * self.cell;
+            if found.len() == k && found[k - 1].0 <= reach {
+                break;
+            }
+            for &candidate in self.ring(center, radius) {
+                if candidate == point {
+                    continue;
+                }
+                let distance = point.distance(candidate);
+                if found.len() < k || distance < found[found.len() - 1].0 {
+                    let at = found.partition_point(|&(d, _)| d <= distance);
+                    found.insert(at, (distance, candidate));
+                    found.truncate(k);
+                }
+            }
+        }
+        found.into_iter().map(|(_, pt)| pt).collect()
+    }
+
+    /// The point closest to `point`, other than itself
+    pub fn nearest(&self, point: P) -> Option<P> {
+        self.k_nearest(point, 1).pop()
+    }
+}
+
+/// The few closest stops to each stop, by the metric
+#[derive(Clone, Debug, Default)]
+pub struct Neighbors<P = MapPoint> {
+    candidates: HashMap<P, Vec<P>>,
+}
+
+impl<P: Stop> Neighbors<P> {
+    pub fn new(stops: &[P], metric: &Metric<P>) -> Self {
+        let grid = Grid::new(stops);
+        let candidates = stops
+            .par_iter()
+            .map(|&stop| {
+                let mut nearest = match metric {
+                    Metric::Euclidean => grid.k_nearest(stop, CANDIDATES),
+                    // Detours only make stops further apart, so the closest ones around obstacles
+                    // are most likely among the closest ones as the crow flies
+                    _ => {
+                        let mut nearest = grid.k_nearest(stop, 2 * CANDIDATES);
+                        nearest.sort_by_key(|&pt| OrderedFloat(metric.distance(stop, pt)));
+                        nearest.truncate(CANDIDATES);
+                        nearest
+                    }
+                };
+                nearest.shrink_to_fit();
+                (stop, nearest)
+            })
+            .collect();
+        Neighbors { candidates }
+    }
+
+    /// The candidates of `stop`, closest first
+    pub fn of(&self, stop: P) -> &[P] {
+        self.candidates
+            .get(&stop)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,130 @@
+use crate::genealogy::{Mutation, Outcome};
+use std::fmt;
+use std::fmt::Write as _;
+
+/// How often one mutation operator has been applied and what came of it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counters {
+    pub applications: u64,
+    /// How many applications improved the solution
+    pub improvements: u64,
+    /// How many applications made the solution worse, but were kept anyway
+    pub worse_accepted: u64,
+    /// Sum of the changes in score the applications made, kept or not, each relative to the
+    /// score before
+    pub delta: f64,
+}
+
+impl Counters {
+    fn record(&mut self, outcome: Outcome, delta: f64) {
+        self.applications += 1;
+        match outcome {
+            Outcome::Improved => self.improvements += 1,
+            Outcome::Worse => self.worse_accepted += 1,
+            Outcome::Rejected => {}
+        }
+        self.delta += delta;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.applications += other.applications;
+        self.improvements += other.improvements;
+        self.worse_accepted += other.worse_accepted;
+        self.delta += other.delta;
+    }
+
+    /// Share of the applications that improved the solution, in percent
+    pub fn improvement_rate(&self) -> f64 {
+        self.percent(self.improvements)
+    }
+
+    /// Share of the applications that made the solution worse but were kept, in percent
+    pub fn acceptance_rate(&self) -> f64 {
+        self.percent(self.worse_accepted)
+    }
+
+    /// Average change in score an application made, in percent of the score before
+    pub fn average_delta(&self) -> f64 {
+        if self.applications == 0 {
+            0.0
+        } else {
+            100.0 * self.delta / self.applications as f64
+        }
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        if self.applications == 0 {
+            0.0
+        } else {
+            100.0 * count as f64 / self.applications as f64
+        }
+    }
+}
+
+impl fmt::Display for Counters {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} applied, {:.1}% improved, {:.1}% worse kept, {:+.4}% score",
+            self.applications,
+            self.improvement_rate(),
+            self.acceptance_rate(),
+            self.average_delta()
+        )
+    }
+}
+
+/// Counters for each mutation operator
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    counters: [Counters; Mutation::ALL.len()],
+}
+
+impl Stats {
+    pub fn record(&mut self, mutation: Mutation, outcome: Outcome, delta: f64) {
+        self.counters[mutation as usize].record(outcome, delta);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (counters, other) in self.counters.iter_mut().zip(&other.counters) {
+            counters.merge(other);
+        }
+    }
+
+    pub fn get(&self, mutation: Mutation) -> &Counters {
+        &self.counters[mutation as usize]
+    }
+
+    /// A table of the counters, one operator per line
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{:<18}{:>12}{:>10}{:>12}{:>12}\n",
+            "operator", "applied", "improved", "worse kept", "avg score"
+        );
+        for mutation in Mutation::ALL {
+            let counters = self.get(mutation);
+            let _ = writeln!(
+                summary,
+                "{:<18}{:>12}{:>9.1}%{:>11.1}%{:>+11.4}%",
+                mutation.name(),
+                counters.applications,
+                counters.improvement_rate(),
+                counters.acceptance_rate(),
+                counters.average_delta()
+            );
+        }
+        summary
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, mutation) in Mutation::ALL.iter().enumerate() {
+            if idx > 0 {
+                write!(fmt, " | ")?;
+            }
+            write!(fmt, "{}: {}", mutation.name(), self.get(*mutation))?;
+        }
+        Ok(())
+    }
+}
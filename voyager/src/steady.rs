@@ -0,0 +1,132 @@
+use crate::chromosome::{self, Chromosome, Scratch};
+use crate::map::Stop;
+use rand::distributions::{WeightedError, WeightedIndex};
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::str::FromStr;
+
+/// How many solutions a replacement tournament draws, the worst of them is replaced
+const TOURNAMENT_SIZE: usize = 4;
+
+/// Which solutions the offspring of a steady-state GA replace. The best solution never is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Replacement {
+    /// The worst ones
+    Worst,
+    /// The ones that joined the population the longest ago
+    Oldest,
+    /// Any of them, at random
+    Random,
+    /// The worst of a few drawn at random
+    Tournament,
+}
+
+impl FromStr for Replacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "worst" => Ok(Replacement::Worst),
+            "oldest" => Ok(Replacement::Oldest),
+            "random" => Ok(Replacement::Random),
+            "tournament" => Ok(Replacement::Tournament),
+            _ => Err(format!(
+                "unknown replacement {:?}, use worst, oldest, random or tournament",
+                s
+            )),
+        }
+    }
+}
+
+/// A GA that breeds a few offspring each generation, which take the place of as many solutions,
+/// rather than replacing the whole population
+#[derive(Clone, Debug)]
+pub struct SteadyState<P> {
+    replacement: Replacement,
+    /// The offspring bred each generation. They're swapped with the solutions they replace, so
+    /// those are overwritten by the next ones.
+    offspring: Vec<Chromosome<P>>,
+    /// How many offspring are bred each generation
+    count: usize,
+    /// The positions of the solutions the offspring replace
+    victims: Vec<usize>,
+    /// The scratch buffers each thread breeds offspring with
+    pub scratch: Vec<Scratch<P>>,
+    /// How many generations have been bred
+    generation: u64,
+}
+
+impl<P: Stop> SteadyState<P> {
+    pub fn new(count: usize, replacement: Replacement) -> Self {
+        SteadyState {
+            replacement,
+            offspring: Vec::with_capacity(count),
+            count,
+            victims: Vec::with_capacity(count),
+            scratch: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// Breeds a generation's offspring from parents picked by their score, and puts them in place
+    /// of the solutions the replacement policy picks. The population is ranked the first time, so
+    /// it starts with its best solution, and still does afterwards.
+    pub fn step(&mut self, population: &mut [Chromosome<P>]) -> Result<(), WeightedError> {
+        if self.generation == 0 {
+            population.par_sort_unstable_by(|a, b| a.cmp(b).reverse());
+        }
+        self.generation += 1;
+        self.offspring.truncate(self.count);
+        self.offspring
+            .resize_with(self.count, || population[0].clone());
+        let score: Vec<f64> = population.iter().map(|c| c.score).collect();
+        let dist = WeightedIndex::new(&score)?;
+        chromosome::breed(population, &mut self.offspring, &mut self.scratch, |rng| {
+            let a = dist.sample(rng);
+            let mut b = dist.sample(rng);
+            while a == b {
+                b = dist.sample(rng);
+            }
+            (a, b)
+        });
+
+        self.pick_victims(population, &mut thread_rng());
+        for (child, &victim) in self.offspring.iter_mut().zip(&self.victims) {
+            child.joined = self.generation;
+            std::mem::swap(&mut population[victim], child);
+            if population[victim] > population[0] {
+                population.swap(0, victim);
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks a solution for each of the offspring to replace, never the best one and never the
+    /// same one twice
+    fn pick_victims(&mut self, population: &[Chromosome<P>], rng: &mut impl Rng) {
+        let len = population.len();
+        self.victims.clear();
+        for _ in 0..self.count.min(len.saturating_sub(1)) {
+            let victims = &self.victims;
+            let mut draw = || loop {
+                let idx = rng.gen_range(1..len);
+                if !victims.contains(&idx) {
+                    break idx;
+                }
+            };
+            let victim = match self.replacement {
+                Replacement::Worst => (1..len)
+                    .filter(|idx| !victims.contains(idx))
+                    .min_by(|&a, &b| population[a].cmp(&population[b])),
+                Replacement::Oldest => (1..len)
+                    .filter(|idx| !victims.contains(idx))
+                    .min_by_key(|&idx| population[idx].joined),
+                Replacement::Random => Some(draw()),
+                Replacement::Tournament => (0..TOURNAMENT_SIZE)
+                    .map(|_| draw())
+                    .min_by(|&a, &b| population[a].cmp(&population[b])),
+            };
+            self.victims.extend(victim);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::chromosome::Chromosome;
+use crate::map::Stop;
+use crate::solver::Move;
+use ordered_float::OrderedFloat;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// How many moves are made each generation
+const MOVES_PER_GENERATION: usize = 4;
+/// How many moves are sampled to pick each move from, each bringing a random stop and one of its
+/// candidates together
+const CANDIDATES: usize = 64;
+/// For how many moves a stop can't be moved again after it's been moved
+const TENURE: u64 = 16;
+
+/// Tabu search over a population of two: the best tour found so far, and the current one the
+/// search moves from
+#[derive(Clone, Debug, Default)]
+pub struct Tabu<P> {
+    /// Stops moved recently, and the move after which they may be moved again
+    tabu: HashMap<P, u64>,
+    /// How many moves have been made
+    moves: u64,
+}
+
+impl<P: Stop> Tabu<P> {
+    /// Whether `mv` would move a stop that was moved too recently
+    fn is_tabu(&self, chromosome: &Chromosome<P>, mv: Move) -> bool {
+        let (a, b) = mv.ends();
+        [a, b].iter().any(|&idx| {
+            self.tabu
+                .get(&chromosome.solution[idx])
+                .is_some_and(|&until| until > self.moves)
+        })
+    }
+
+    /// Makes a generation's worth of moves from the current tour, each to the best of a sample of
+    /// its neighbors even if it's worse. Tabu moves are only made when they lead to a tour better
+    /// than any found so far.
+    pub fn step(&mut self, population: &mut [Chromosome<P>]) {
+        let (best, current) = match population {
+            [best, current, ..] => (best, current),
+            _ => return,
+        };
+        let mut rng = thread_rng();
+        let free = current.problem.mode.free_range(current.solution.len());
+        for _ in 0..MOVES_PER_GENERATION {
+            let moves: Vec<_> = (0..CANDIDATES)
+                .filter_map(|_| Move::near(current, free.clone(), &mut rng))
+                .collect();
+            let chosen = moves
+                .into_par_iter()
+                .map(|mv| (mv, mv.apply(current)))
+                .filter(|(mv, neighbor)| !self.is_tabu(current, *mv) || neighbor.score > best.score)
+                .max_by_key(|(_, neighbor)| OrderedFloat(neighbor.score));
+            if let Some((mv, neighbor)) = chosen {
+                let (a, b) = mv.ends();
+                for idx in [a, b] {
+                    self.tabu.insert(current.solution[idx], self.moves + TENURE);
+                }
+                *current = neighbor;
+                if current.score > best.score {
+                    *best = current.clone();
+                }
+            }
+            self.moves += 1;
+        }
+        let moves = self.moves;
+        self.tabu.retain(|_, &mut until| until > moves);
+    }
+}
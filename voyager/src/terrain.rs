@@ -0,0 +1,366 @@
+use crate::map::{self, MapPoint, Stop};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+const EPSILON: f64 = 1e-9;
+const NO_PREDECESSOR: usize = usize::MAX;
+
+/// An obstacle, as the vertices of a simple polygon in order. Stops can't be placed inside one,
+/// and paths between stops go around it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polygon(pub Vec<MapPoint>);
+
+#[inline(always)]
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+/// Whether the segments (p1, p2) and (q1, q2) cross at a single point interior to both
+fn crosses(p1: (f64, f64), p2: (f64, f64), q1: (f64, f64), q2: (f64, f64)) -> bool {
+    let straddles = |a: f64, b: f64| (a > EPSILON && b < -EPSILON) || (a < -EPSILON && b > EPSILON);
+    straddles(cross(q1, q2, p1), cross(q1, q2, p2))
+        && straddles(cross(p1, p2, q1), cross(p1, p2, q2))
+}
+
+/// Where along the segment (a, b) the point p lies, if it lies on it at all
+fn project(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> Option<f64> {
+    let length = (b.0 - a.0).powi(2) + (b.1 - a.1).powi(2);
+    if length < EPSILON || cross(a, b, p).abs() > EPSILON * length.sqrt().max(1.0) {
+        return None;
+    }
+    let t = ((p.0 - a.0) * (b.0 - a.0) + (p.1 - a.1) * (b.1 - a.1)) / length;
+    Some(t).filter(|t| (0.0..=1.0).contains(t))
+}
+
+impl Polygon {
+    fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        let next = self.0.iter().cycle().skip(1);
+        self.0.iter().zip(next).map(|(a, b)| (a.xy(), b.xy()))
+    }
+
+    fn on_boundary(&self, p: (f64, f64)) -> bool {
+        self.edges().any(|(a, b)| project(a, b, p).is_some())
+    }
+
+    fn contains_xy(&self, p: (f64, f64)) -> bool {
+        if self.on_boundary(p) {
+            return false;
+        }
+        // Even-odd rule, counting crossings of a ray going right from p
+        self.edges()
+            .filter(|(a, b)| (a.1 > p.1) != (b.1 > p.1))
+            .filter(|(a, b)| p.0 < a.0 + (p.1 - a.1) * (b.0 - a.0) / (b.1 - a.1))
+            .count()
+            % 2
+            == 1
+    }
+
+    /// Whether `point` is strictly inside the polygon, points on its boundary are outside. Only
+    /// where it falls on the plane of the first two axes counts.
+    pub fn contains(&self, point: &impl Stop) -> bool {
+        self.contains_xy(point.xy())
+    }
+
+    /// Whether the segment from `a` to `b` passes through the polygon's interior. Touching or
+    /// running along its boundary doesn't count.
+    fn blocks(&self, a: (f64, f64), b: (f64, f64)) -> bool {
+        if self.edges().any(|(p, q)| crosses(a, b, p, q)) {
+            return true;
+        }
+        // Without a proper crossing the segment can still enter the polygon through its
+        // vertices, so we check the pieces between any vertices it touches.
+        let mut cuts: Vec<f64> = self
+            .0
+            .iter()
+            .filter_map(|v| project(a, b, v.xy()))
+            .collect();
+        cuts.push(0.0);
+        cuts.push(1.0);
+        cuts.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        cuts.windows(2).filter(|w| w[1] - w[0] > EPSILON).any(|w| {
+            let t = (w[0] + w[1]) / 2.0;
+            self.contains_xy((a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)))
+        })
+    }
+}
+
+impl FromStr for Polygon {
+    type Err = Box<dyn std::error::Error>;
+
+    /// Parses whitespace separated `x,y` vertices
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let vertices = s
+            .split_whitespace()
+            .map(|vertex| {
+                let (x, y) = map::parse_pair(vertex)?;
+                Ok(MapPoint::new(x.into(), y.into()))
+            })
+            .collect::<Result<Vec<_>, Self::Err>>()?;
+        if vertices.len() < 3 {
+            return Err(format!(
+                "a polygon needs at least 3 vertices, got {}",
+                vertices.len()
+            )
+            .into());
+        }
+        Ok(Polygon(vertices))
+    }
+}
+
+/// Loads obstacles from a file with one polygon per line. Blank lines and lines starting with `#`
+/// are ignored.
+pub fn load(path: &Path) -> Result<Vec<Polygon>, Box<dyn std::error::Error>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+/// Shortest paths between every pair of stops that avoid a set of obstacles. They're found on
+/// the visibility graph, whose nodes are the stops and the obstacles' vertices, and whose edges
+/// are the straight lines between nodes which don't pass through any obstacle. Paths between
+/// vertices are found once, so a stop that moves only needs its own paths found again. Obstacles
+/// only make sense on flat maps, the vertices of any other kind are placed on the plane of the
+/// first two axes.
+#[derive(Clone, Debug)]
+pub struct Terrain<P = MapPoint> {
+    obstacles: Vec<Polygon>,
+    vertices: Vec<P>,
+    /// Row-major matrix of path lengths between vertices
+    hops: Vec<f64>,
+    /// Row-major matrix of the vertex after each one on the shortest path to each other one
+    next: Vec<usize>,
+    /// Maps each stop to its row in `reach` and `costs`
+    index: HashMap<P, usize>,
+    /// Row-major matrix of path lengths from each stop to each vertex
+    reach: Vec<f64>,
+    /// Row-major matrix of the first vertex on the shortest path from each stop to each vertex
+    entry: Vec<usize>,
+    /// Row-major matrix of path lengths between stops
+    costs: Vec<f64>,
+}
+
+impl<P: Stop> Terrain<P> {
+    pub fn new(obstacles: &[Polygon], stops: &[P]) -> Self {
+        let vertices: Vec<P> = obstacles
+            .iter()
+            .flat_map(|polygon| polygon.0.iter())
+            .map(|v| P::from_coords(&v.coords()))
+            .collect();
+        let count = vertices.len();
+
+        // Floyd-Warshall over the vertices, starting from the edges between the visible ones
+        let (mut hops, mut next): (Vec<f64>, Vec<usize>) = (0..(count * count))
+            .into_par_iter()
+            .map(|idx| {
+                let (u, v) = (idx / count, idx % count);
+                if u == v {
+                    (0.0, v)
+                } else if visible(obstacles, vertices[u].xy(), vertices[v].xy()) {
+                    (vertices[u].distance(vertices[v]), v)
+                } else {
+                    (f64::INFINITY, NO_PREDECESSOR)
+                }
+            })
+            .unzip();
+        for k in 0..count {
+            for u in 0..count {
+                let via = hops[u * count + k];
+                if !via.is_finite() {
+                    continue;
+                }
+                for v in 0..count {
+                    let candidate = via + hops[k * count + v];
+                    if candidate < hops[u * count + v] {
+                        hops[u * count + v] = candidate;
+                        next[u * count + v] = next[u * count + k];
+                    }
+                }
+            }
+        }
+
+        let mut terrain = Terrain {
+            obstacles: obstacles.to_vec(),
+            vertices,
+            hops,
+            next,
+            index: stops
+                .iter()
+                .enumerate()
+                .map(|(idx, pt)| (*pt, idx))
+                .collect(),
+            reach: Vec::new(),
+            entry: Vec::new(),
+            costs: Vec::new(),
+        };
+        let (reach, entry): (Vec<Vec<f64>>, Vec<Vec<usize>>) = stops
+            .par_iter()
+            .map(|&stop| terrain.reach_from(stop))
+            .unzip();
+        terrain.reach = reach.concat();
+        terrain.entry = entry.concat();
+        terrain.costs = (0..(stops.len() * stops.len()))
+            .into_par_iter()
+            .map(|idx| {
+                let (a, b) = (idx / stops.len(), idx % stops.len());
+                terrain.cost(stops[a], a, stops[b], b)
+            })
+            .collect();
+        terrain
+    }
+
+    /// Lengths of the shortest paths from `point` to each vertex, and the first vertex on each
+    fn reach_from(&self, point: P) -> (Vec<f64>, Vec<usize>) {
+        let count = self.vertices.len();
+        let mut reach = vec![f64::INFINITY; count];
+        let mut entry = vec![NO_PREDECESSOR; count];
+        for (u, &vertex) in self.vertices.iter().enumerate() {
+            if !visible(&self.obstacles, point.xy(), vertex.xy()) {
+                continue;
+            }
+            let first = point.distance(vertex);
+            for v in 0..count {
+                let candidate = first + self.hops[u * count + v];
+                if candidate < reach[v] {
+                    reach[v] = candidate;
+                    entry[v] = u;
+                }
+            }
+        }
+        (reach, entry)
+    }
+
+    /// The row of `reach` for the stop at row `idx`
+    fn reach_row(&self, idx: usize) -> &[f64] {
+        let count = self.vertices.len();
+        &self.reach[(idx * count)..((idx + 1) * count)]
+    }
+
+    /// The vertex a shortest path between the stops at rows `a` and `b` passes through, and its
+    /// length. There's none if the stops are out of reach of each other.
+    fn meeting(&self, a: usize, b: usize) -> Option<(usize, f64)> {
+        self.reach_row(a)
+            .iter()
+            .zip(self.reach_row(b))
+            .map(|(x, y)| x + y)
+            .enumerate()
+            .filter(|(_, length)| length.is_finite())
+            .min_by(|x, y| x.1.partial_cmp(&y.1).unwrap())
+    }
+
+    /// Length of the shortest path from stop `a` to stop `b`, given their rows
+    fn cost(&self, a: P, a_idx: usize, b: P, b_idx: usize) -> f64 {
+        if a_idx == b_idx {
+            0.0
+        } else if visible(&self.obstacles, a.xy(), b.xy()) {
+            a.distance(b)
+        } else {
+            self.meeting(a_idx, b_idx)
+                .map_or(f64::INFINITY, |(_, length)| length)
+        }
+    }
+
+    /// Finds the paths of a stop that moved from `from` to `to` again. The other stops' paths
+    /// between each other stay as they were, since stops don't block anything.
+    pub fn moved(&mut self, from: P, to: P) {
+        let idx = match self.index.remove(&from) {
+            Some(idx) => idx,
+            None => return,
+        };
+        self.index.insert(to, idx);
+        let count = self.vertices.len();
+        let (reach, entry) = self.reach_from(to);
+        self.reach[(idx * count)..((idx + 1) * count)].copy_from_slice(&reach);
+        self.entry[(idx * count)..((idx + 1) * count)].copy_from_slice(&entry);
+        let stops: Vec<(P, usize)> = self.index.iter().map(|(&pt, &row)| (pt, row)).collect();
+        let row: Vec<(usize, f64)> = stops
+            .par_iter()
+            .map(|&(stop, other)| (other, self.cost(to, idx, stop, other)))
+            .collect();
+        let len = self.index.len();
+        for (other, cost) in row {
+            self.costs[idx * len + other] = cost;
+            self.costs[other * len + idx] = cost;
+        }
+    }
+
+    /// Whether there's a path from `point` to any of the stops, so a stop put there could be
+    /// visited
+    pub fn reaches(&self, point: P) -> bool {
+        let (reach, _) = self.reach_from(point);
+        self.index.iter().any(|(&stop, &idx)| {
+            visible(&self.obstacles, point.xy(), stop.xy())
+                || reach
+                    .iter()
+                    .zip(self.reach_row(idx))
+                    .any(|(x, y)| (x + y).is_finite())
+        })
+    }
+
+    /// Two stops there's no path between, if there are any
+    pub fn unreachable(&self) -> Option<(P, P)> {
+        let len = self.index.len();
+        let idx = self.costs.iter().position(|cost| !cost.is_finite())?;
+        let stop = |row| {
+            self.index
+                .iter()
+                .find(|(_, &idx)| idx == row)
+                .map(|(pt, _)| *pt)
+        };
+        Some((stop(idx / len)?, stop(idx % len)?))
+    }
+
+    /// Length of the shortest path between two stops. Stops the terrain wasn't built for are
+    /// measured as the crow flies.
+    #[inline]
+    pub fn distance(&self, a: P, b: P) -> f64 {
+        match (self.index.get(&a), self.index.get(&b)) {
+            (Some(&a), Some(&b)) => self.costs[a * self.index.len() + b],
+            _ => a.distance(b),
+        }
+    }
+
+    /// The vertices on the shortest path from vertex `u` to vertex `v`, both included
+    fn hop_path(&self, mut u: usize, v: usize) -> Vec<P> {
+        let count = self.vertices.len();
+        let mut path = vec![self.vertices[u]];
+        while u != v {
+            u = self.next[u * count + v];
+            path.push(self.vertices[u]);
+        }
+        path
+    }
+
+    /// The polyline of the shortest path between two stops, including both of them
+    pub fn path(&self, a: P, b: P) -> Vec<P> {
+        let (a_idx, b_idx) = match (self.index.get(&a), self.index.get(&b)) {
+            (Some(&a), Some(&b)) => (a, b),
+            _ => return vec![a, b],
+        };
+        if visible(&self.obstacles, a.xy(), b.xy()) {
+            return vec![a, b];
+        }
+        // There's no way around the obstacles, so there's no path to draw either
+        let meet = match self.meeting(a_idx, b_idx) {
+            Some((meet, _)) => meet,
+            None => return vec![a, b],
+        };
+        let count = self.vertices.len();
+        let mut path = vec![a];
+        path.extend(self.hop_path(self.entry[a_idx * count + meet], meet));
+        let mut back = self.hop_path(self.entry[b_idx * count + meet], meet);
+        back.pop();
+        path.extend(back.into_iter().rev());
+        path.push(b);
+        path
+    }
+}
+
+/// Whether the segment from `a` to `b` doesn't pass through any of `obstacles`
+fn visible(obstacles: &[Polygon], a: (f64, f64), b: (f64, f64)) -> bool {
+    !obstacles.iter().any(|polygon| polygon.blocks(a, b))
+}
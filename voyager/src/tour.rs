@@ -0,0 +1,155 @@
+use crate::chromosome::Chromosome;
+use crate::geo;
+use crate::map::{self, Map, Stop, StopIds};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// How a file lists stops, going by its extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// A row per stop and a column per coordinate, under an optional header
+    Csv,
+    /// An array of coordinate arrays, on its own or as the `stops` of an object
+    Json,
+    /// The way maps are displayed, `(x, y) -> (x, y) -> ...`
+    Text,
+    /// A FeatureCollection of Points in longitude and latitude
+    GeoJson,
+}
+
+fn format(path: &Path) -> Result<Format, Box<dyn Error>> {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("csv") => Ok(Format::Csv),
+        Some("json") => Ok(Format::Json),
+        Some("txt") => Ok(Format::Text),
+        Some("geojson") => Ok(Format::GeoJson),
+        _ => Err(format!(
+            "unknown format for {}, use .csv, .json, .txt or .geojson",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+/// Name of the CSV column holding a coordinate
+fn axis(axis: usize) -> String {
+    match axis {
+        0 => "x".to_string(),
+        1 => "y".to_string(),
+        2 => "z".to_string(),
+        n => format!("x{}", n + 1),
+    }
+}
+
+fn parse_csv<P: Stop>(csv: &str) -> Result<Vec<P>, Box<dyn Error>> {
+    let mut lines = csv
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .peekable();
+    // A first line without a single number is taken for the header, one with a typo isn't
+    if let Some((_, first)) = lines.peek() {
+        let header = first
+            .split(',')
+            .all(|field| field.trim().parse::<f64>().is_err());
+        if header {
+            lines.next();
+        }
+    }
+    lines
+        .map(|(idx, line)| {
+            map::parse_point(line).map_err(|err| format!("line {}: {}", idx + 1, err).into())
+        })
+        .collect()
+}
+
+fn parse_json<P: Stop>(json: &str) -> Result<Vec<P>, Box<dyn Error>> {
+    let value: Value = serde_json::from_str(json)?;
+    let stops = value
+        .get("stops")
+        .unwrap_or(&value)
+        .as_array()
+        .ok_or("expected an array of stops, or an object with one as its \"stops\"")?;
+    stops
+        .iter()
+        .map(|stop| {
+            let coords = stop
+                .as_array()
+                .and_then(|coords| coords.iter().map(Value::as_f64).collect::<Option<Vec<_>>>())
+                .ok_or_else(|| format!("expected a stop like [x, y], got {}", stop))?;
+            if coords.len() != P::DIMENSIONS {
+                return Err(format!(
+                    "expected a stop with {} coordinates, got {}",
+                    P::DIMENSIONS,
+                    stop
+                )
+                .into());
+            }
+            Ok(P::from_coords(&coords))
+        })
+        .collect()
+}
+
+/// Whether a file's stops are longitudes and latitudes
+pub fn is_geographic(path: &Path) -> bool {
+    matches!(format(path), Ok(Format::GeoJson))
+}
+
+/// Loads stops from a CSV, JSON, text or GeoJSON file in the order it lists them, so a tour saved
+/// by one run can seed another. GeoJSON features also name their stops, by their `id_property`
+/// or by their own ids without one.
+pub fn load<P: Stop>(
+    path: &Path,
+    id_property: Option<&str>,
+) -> Result<(Map<P>, StopIds<P>), Box<dyn Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    let (stops, ids) = match format(path)? {
+        Format::Csv => (parse_csv(&contents)?, StopIds::new()),
+        Format::Json => (parse_json(&contents)?, StopIds::new()),
+        Format::Text => (contents.parse::<Map<P>>()?.0, StopIds::new()),
+        Format::GeoJson => geo::parse(&contents, id_property)?,
+    };
+    if stops.is_empty() {
+        return Err(format!("{} has no stops", path.display()).into());
+    }
+    let mut seen = HashSet::new();
+    if let Some(stop) = stops.iter().find(|&&stop| !seen.insert(stop)) {
+        return Err(format!("{} appears twice in {}", stop, path.display()).into());
+    }
+    Ok((Map(stops), ids))
+}
+
+/// Saves a tour's stops in the order it visits them as a CSV, JSON, text or GeoJSON file depending
+/// on the extension. JSON files also get the tour's length, and GeoJSON ones the length of each
+/// leg too.
+pub fn save<P: Stop>(path: &Path, chromosome: &Chromosome<P>) -> Result<(), Box<dyn Error>> {
+    let contents = match format(path)? {
+        Format::Csv => {
+            let mut csv = (0..P::DIMENSIONS).map(axis).collect::<Vec<_>>().join(",");
+            csv += "\n";
+            for stop in chromosome.solution.iter() {
+                let coords: Vec<_> = stop.coords().iter().map(f64::to_string).collect();
+                writeln!(csv, "{}", coords.join(","))?;
+            }
+            csv
+        }
+        Format::Json => {
+            let stops: Vec<_> = chromosome
+                .solution
+                .iter()
+                .map(|stop| stop.coords())
+                .collect();
+            let json = json!({ "length": chromosome.length(), "stops": stops });
+            serde_json::to_string(&json)? + "\n"
+        }
+        Format::Text => format!("{}\n", chromosome),
+        Format::GeoJson => serde_json::to_string(&geo::feature_collection(chromosome))? + "\n",
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}